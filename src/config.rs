@@ -1,34 +1,324 @@
+pub mod watch;
+
+use std::collections::HashSet;
 use std::env::var;
 use axum::http::Uri;
-use serde::Deserialize;
-use std::path::Path;
-use hyper::server::conn::Http;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use toml::de::Error;
 use std::str::FromStr;
 use crate::config::HttpMethod::{DELETE, GET, HEAD, OPTIONS, PATCH, POST, PUT};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Config {
     pub reload_cron: String,
+    #[serde(default)]
+    pub reload: ReloadConfig,
     pub openapi_urls: Vec<OpenApiConfig>,
     #[serde(default)]
-    pub global_cors: Option<CorsConfig>
+    pub openapi_directories: Vec<DirectoryConfig>,
+    #[serde(default)]
+    pub global_cors: Option<CorsConfig>,
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// Directory where fetched OpenAPI files are kept, used at startup when fetching fails
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Externally visible URL of the gateway, served OpenAPI files point at it
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Request header identifying the consumer in the usage of deprecated operations, the client IP otherwise
+    #[serde(default)]
+    pub consumer_header: Option<String>,
+    /// Files in `swagger-ui/` and `redoc/` of this directory are served instead of the built-in ones
+    #[serde(default)]
+    pub ui_dir: Option<PathBuf>,
+}
+
+/// Difference between the running config and a reloaded config file
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added: Vec<OpenApiConfig>,
+    pub removed: Vec<String>,
+    pub changed: Vec<OpenApiConfig>,
+    pub cors_changed: bool,
+    pub reload_cron_changed: bool,
+    pub reload_changed: bool,
+    pub public_url_changed: bool,
+    pub ui_dir_changed: bool,
+    /// Sections that only take effect after a restart
+    pub restart_required: Vec<&'static str>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct OpenApiConfig {
     pub name: String,
+    /// Identifies the entry in URLs, e.g. `/docs/defs/{slug}.json`, derived from `name` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
     pub url: String,
+    /// Optional entries are not required for the gateway to report ready
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+    /// Scheme and authority requests are proxied to, required for `file://` URLs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// Overrides the global `reload_cron` for this entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reload_cron: Option<String>,
+    /// Entries with `reload = false` are only fetched at startup or when a reload is forced
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub reload: bool,
+    /// Breaking changes to the OpenAPI file are only applied once acknowledged through the admin API
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub block_breaking_changes: bool,
+    /// Only operations with one of these tags are exposed, or one of `include_operations`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tags: Vec<String>,
+    /// Operation ids of exposed operations, or those with one of `include_tags`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_operations: Vec<String>,
+    /// Globs of paths as written in the OpenAPI file, `*` matches within a segment and `**` across segments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_paths: Vec<String>,
+    /// Operations and paths with one of these extensions set to `true` are neither routed nor published
+    #[serde(default = "default_hidden_extensions", skip_serializing_if = "is_default_hidden_extensions")]
+    pub hidden_extensions: Vec<String>,
+    #[serde(default, skip_serializing_if = "DeprecatedPolicy::is_pass")]
+    pub deprecated_operations: DeprecatedPolicy,
+    /// Directory the file was found in, these entries are not written to the config file
+    #[serde(skip)]
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for OpenApiConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            slug: None,
+            url: String::new(),
+            optional: false,
+            upstream: None,
+            reload_cron: None,
+            reload: true,
+            block_breaking_changes: false,
+            include_tags: vec![],
+            exclude_tags: vec![],
+            include_operations: vec![],
+            exclude_paths: vec![],
+            hidden_extensions: default_hidden_extensions(),
+            deprecated_operations: DeprecatedPolicy::Pass,
+            directory: None,
+        }
+    }
+}
+
+/// What happens to requests to operations marked `deprecated: true`
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeprecatedPolicy {
+    /// Proxied as any other operation
+    #[default]
+    Pass,
+    /// Proxied, responses get a `Deprecation` header and a `Sunset` header from `x-sunset`
+    Headers,
+    /// Answered with `410 Gone`
+    Reject,
+}
+
+/// Loads every `*.json`, `*.yaml` and `*.yml` file in a directory as entry
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DirectoryConfig {
+    pub path: PathBuf,
+    /// Scheme and authority requests to routes of these files are proxied to
+    pub upstream: String,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// Timeout and backoff used when fetching the OpenAPI files
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReloadConfig {
+    #[serde(default = "default_fetch_timeout_secs")]
+    pub fetch_timeout_secs: u64,
+    /// Delay before retrying an entry after its first failure, doubled on every next failure
+    #[serde(default = "default_backoff_initial_secs")]
+    pub backoff_initial_secs: u64,
+    #[serde(default = "default_backoff_max_secs")]
+    pub backoff_max_secs: u64,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            fetch_timeout_secs: default_fetch_timeout_secs(),
+            backoff_initial_secs: default_backoff_initial_secs(),
+            backoff_max_secs: default_backoff_max_secs(),
+        }
+    }
+}
+
+fn default_fetch_timeout_secs() -> u64 {
+    10
+}
+
+fn default_backoff_initial_secs() -> u64 {
+    60
 }
 
-#[derive(Deserialize, Debug, Clone)]
+fn default_backoff_max_secs() -> u64 {
+    3600
+}
+
+/// Paths of the health endpoints, these take precedence over proxied routes
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HealthConfig {
+    #[serde(default = "default_liveness_path")]
+    pub liveness_path: String,
+    #[serde(default = "default_readiness_path")]
+    pub readiness_path: String,
+}
+
+impl HealthConfig {
+    pub fn paths(&self) -> Vec<String> {
+        vec![self.liveness_path.clone(), self.readiness_path.clone()]
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            liveness_path: default_liveness_path(),
+            readiness_path: default_readiness_path(),
+        }
+    }
+}
+
+/// Admin API, only enabled when configured
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AdminConfig {
+    /// Bearer token required on every admin request
+    pub token: String,
+    /// Serve the admin API on a separate port instead of under `path` on the gateway port
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default = "default_admin_path")]
+    pub path: String,
+    /// Write entries changed through the admin API back to the config file
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn default_admin_path() -> String {
+    "/admin".to_string()
+}
+
+fn default_liveness_path() -> String {
+    "/healthz".to_string()
+}
+
+fn default_readiness_path() -> String {
+    "/readyz".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct CorsConfig {
     pub allowed_origin: String,
     pub allowed_methods: Vec<HttpMethod>,
     pub allowed_headers: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// OTLP trace export, only used when built with the `otlp` feature
+#[cfg_attr(not(feature = "otlp"), allow(dead_code))]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TelemetryConfig {
+    /// Base URL of the OTLP/HTTP collector, e.g. `http://localhost:4318`
+    pub otlp_endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    env!("CARGO_PKG_NAME").to_string()
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Query parameters of which the value is replaced before logging, matched case-insensitive
+    #[serde(default)]
+    pub redact_query_params: Vec<String>,
+    /// Request headers that are logged, others may carry credentials and are left out
+    #[serde(default = "default_log_headers")]
+    pub log_headers: Vec<String>,
+    /// Request headers of which the value is replaced before logging, matched case-insensitive
+    #[serde(default = "default_redact_headers")]
+    pub redact_headers: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    #[default]
+    Json,
+    Common,
+    Combined,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: AccessLogFormat::default(),
+            redact_query_params: vec![],
+            log_headers: default_log_headers(),
+            redact_headers: default_redact_headers(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn default_hidden_extensions() -> Vec<String> {
+    vec!["x-internal".to_string(), "x-gateway-hidden".to_string()]
+}
+
+fn is_default_hidden_extensions(value: &Vec<String>) -> bool {
+    *value == default_hidden_extensions()
+}
+
+fn default_log_headers() -> Vec<String> {
+    vec!["referer".to_string(), "user-agent".to_string(), "x-request-id".to_string()]
+}
+
+fn default_redact_headers() -> Vec<String> {
+    vec!["authorization".to_string(), "cookie".to_string(), "proxy-authorization".to_string()]
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub enum HttpMethod {
     GET,
     POST,
@@ -58,6 +348,42 @@ impl FromStr for HttpMethod {
     }
 }
 
+impl DeprecatedPolicy {
+    pub fn is_pass(&self) -> bool {
+        *self == DeprecatedPolicy::Pass
+    }
+}
+
+impl FromStr for DeprecatedPolicy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "pass" => DeprecatedPolicy::Pass,
+            "headers" => DeprecatedPolicy::Headers,
+            "reject" => DeprecatedPolicy::Reject,
+            _ => {
+                return Err(ConfigError::Invalid(format!("invalid deprecated_operations: {s}")))
+            }
+        })
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "json" => AccessLogFormat::Json,
+            "common" => AccessLogFormat::Common,
+            "combined" => AccessLogFormat::Combined,
+            _ => {
+                return Err(ConfigError::InvalidAccessLogFormat(s.to_string()))
+            }
+        })
+    }
+}
+
 impl From<&HttpMethod> for &str {
     fn from(value: &HttpMethod) -> Self {
         match value {
@@ -74,12 +400,22 @@ impl From<&HttpMethod> for &str {
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigError {
-    #[error("TOML error")]
+    #[error("TOML error: {0}")]
     Toml(#[from] Error),
-    #[error("IO error")]
+    #[error("IO error: {0}")]
     IO(#[from] std::io::Error),
     #[error("Invalid HttpMethod")]
-    InvalidHttpMethod(String)
+    InvalidHttpMethod(String),
+    #[error("Invalid access log format")]
+    InvalidAccessLogFormat(String),
+    #[error("Invalid port")]
+    InvalidPort(String),
+    #[error("Invalid OpenAPI URL: {0}")]
+    InvalidUrl(String),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+    #[error("TOML serialize error")]
+    TomlSerialize(#[from] toml::ser::Error)
 }
 
 const CONFIG_ENVIRONMENT_PREFIX: &str = "OPENAPI_";
@@ -94,6 +430,112 @@ impl Config {
         )
     }
 
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_cron(&self.reload_cron)?;
+
+        if self.reload.fetch_timeout_secs == 0 || self.reload.backoff_initial_secs == 0 {
+            return Err(ConfigError::Invalid("reload timeout and backoff must be greater than zero".to_string()));
+        }
+        if self.reload.backoff_max_secs < self.reload.backoff_initial_secs {
+            return Err(ConfigError::Invalid("reload backoff_max_secs must not be less than backoff_initial_secs".to_string()));
+        }
+
+        if let Some(public_url) = &self.public_url {
+            validate_upstream(public_url).map_err(|_| ConfigError::Invalid(format!("invalid public_url: {public_url}")))?;
+        }
+        if let Some(telemetry) = &self.telemetry {
+            validate_upstream(&telemetry.otlp_endpoint)
+                .map_err(|_| ConfigError::Invalid(format!("invalid otlp_endpoint: {}", telemetry.otlp_endpoint)))?;
+        }
+
+        for directory in &self.openapi_directories {
+            if !directory.path.is_dir() {
+                return Err(ConfigError::Invalid(format!("not a directory: {}", directory.path.display())));
+            }
+            validate_upstream(&directory.upstream)?;
+        }
+        if let Some(ui_dir) = self.ui_dir.as_ref().filter(|ui_dir| !ui_dir.is_dir()) {
+            return Err(ConfigError::Invalid(format!("not a directory: {}", ui_dir.display())));
+        }
+
+        let mut names = HashSet::new();
+        let mut slugs = HashSet::new();
+        for openapi_config in &self.openapi_urls {
+            openapi_config.validate()?;
+
+            if !names.insert(&openapi_config.name) {
+                return Err(ConfigError::Invalid(format!("duplicate entry name: {}", openapi_config.name)));
+            }
+            if !slugs.insert(openapi_config.slug()) {
+                return Err(ConfigError::Invalid(format!("duplicate entry slug: {}", openapi_config.slug())));
+            }
+        }
+
+        let mut paths = vec![&self.health.liveness_path, &self.health.readiness_path];
+        if let Some(admin) = &self.admin {
+            if admin.token.is_empty() {
+                return Err(ConfigError::Invalid("admin token must not be empty".to_string()));
+            }
+            paths.push(&admin.path);
+        }
+        if let Some(path) = paths.iter().find(|path| !path.starts_with('/')) {
+            return Err(ConfigError::Invalid(format!("path must start with '/': {path}")));
+        }
+
+        Ok(())
+    }
+
+    /// Entries of `openapi_urls` followed by the files found in `openapi_directories`
+    pub fn entry_configs(&self) -> Vec<OpenApiConfig> {
+        let mut configs = self.openapi_urls.clone();
+
+        for directory in &self.openapi_directories {
+            let files = directory.files().unwrap_or_else(|err| {
+                tracing::warn!("Reading directory {} failed: {err}", directory.path.display());
+                vec![]
+            });
+
+            for file in files {
+                let file_name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+                let title = openapi_title(&file);
+                let taken = |name: &String| configs.iter().any(|config| &config.name == name || config.slug() == slugify(name));
+
+                let name = match title {
+                    Some(title) if !taken(&title) => title,
+                    _ if !taken(&file_name) => file_name,
+                    _ => {
+                        tracing::warn!("Skipping {}, an entry with the same name or slug exists", file.display());
+                        continue;
+                    }
+                };
+
+                configs.push(OpenApiConfig {
+                    name,
+                    url: format!("file://{}", file.display()),
+                    optional: directory.optional,
+                    upstream: Some(directory.upstream.clone()),
+                    directory: Some(directory.path.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+
+        configs
+    }
+
+    /// Replaces `openapi_urls` in the config file, leaving all other settings untouched
+    pub fn persist_openapi_urls<P: AsRef<Path>>(path: P, openapi_urls: &[OpenApiConfig]) -> Result<(), ConfigError> {
+        let mut document: toml::Value = toml::from_str(std::fs::read_to_string(&path)?.as_str())?;
+
+        if let Some(table) = document.as_table_mut() {
+            table.insert("openapi_urls".to_string(), toml::Value::try_from(openapi_urls)?);
+        }
+
+        std::fs::write(path, toml::to_string(&document)?)?;
+
+        Ok(())
+    }
+
     pub fn parse_from_env() -> Result<Config, ConfigError> {
         let mut configs = vec![];
         let mut count = 0u32;
@@ -106,7 +548,32 @@ impl Config {
                     configs.push(
                         OpenApiConfig {
                             name,
+                            slug: var(format!("{}{}_SLUG", CONFIG_ENVIRONMENT_PREFIX, count)).ok(),
                             url,
+                            optional: var(format!("{}{}_OPTIONAL", CONFIG_ENVIRONMENT_PREFIX, count))
+                                .map(|optional| optional.trim() == "true")
+                                .unwrap_or(false),
+                            upstream: var(format!("{}{}_UPSTREAM", CONFIG_ENVIRONMENT_PREFIX, count)).ok(),
+                            reload_cron: var(format!("{}{}_RELOAD_CRON", CONFIG_ENVIRONMENT_PREFIX, count)).ok(),
+                            reload: var(format!("{}{}_RELOAD", CONFIG_ENVIRONMENT_PREFIX, count))
+                                .map(|reload| reload.trim() != "false")
+                                .unwrap_or(true),
+                            block_breaking_changes: var(format!("{}{}_BLOCK_BREAKING_CHANGES", CONFIG_ENVIRONMENT_PREFIX, count))
+                                .map(|block| block.trim() == "true")
+                                .unwrap_or(false),
+                            include_tags: var_list(format!("{}{}_INCLUDE_TAGS", CONFIG_ENVIRONMENT_PREFIX, count)),
+                            exclude_tags: var_list(format!("{}{}_EXCLUDE_TAGS", CONFIG_ENVIRONMENT_PREFIX, count)),
+                            include_operations: var_list(format!("{}{}_INCLUDE_OPERATIONS", CONFIG_ENVIRONMENT_PREFIX, count)),
+                            exclude_paths: var_list(format!("{}{}_EXCLUDE_PATHS", CONFIG_ENVIRONMENT_PREFIX, count)),
+                            hidden_extensions: var(format!("{}{}_HIDDEN_EXTENSIONS", CONFIG_ENVIRONMENT_PREFIX, count))
+                                .map(|extensions| split_list(&extensions))
+                                .unwrap_or_else(|_| default_hidden_extensions()),
+                            deprecated_operations: var(format!("{}{}_DEPRECATED_OPERATIONS", CONFIG_ENVIRONMENT_PREFIX, count))
+                                .ok()
+                                .map(|policy| DeprecatedPolicy::from_str(policy.trim()))
+                                .transpose()?
+                                .unwrap_or_default(),
+                            directory: None,
                         }
                     )
                 }
@@ -127,6 +594,20 @@ impl Config {
             count += 1;
         }
 
+        let mut directories = vec![];
+        while let (Ok(path), Ok(upstream)) = (
+            var(format!("{CONFIG_ENVIRONMENT_PREFIX}DIRECTORY_{}_PATH", directories.len())),
+            var(format!("{CONFIG_ENVIRONMENT_PREFIX}DIRECTORY_{}_UPSTREAM", directories.len())),
+        ) {
+            directories.push(DirectoryConfig {
+                path: PathBuf::from(path),
+                upstream,
+                optional: var(format!("{CONFIG_ENVIRONMENT_PREFIX}DIRECTORY_{}_OPTIONAL", directories.len()))
+                    .map(|optional| optional.trim() == "true")
+                    .unwrap_or(false),
+            });
+        }
+
         let origin_config_key = format!("{CONFIG_ENVIRONMENT_PREFIX}CORS_ALLOWED_ORIGIN");
         let methods_config_key = format!("{CONFIG_ENVIRONMENT_PREFIX}CORS_ALLOWED_METHODS");
         let headers_config_key = format!("{CONFIG_ENVIRONMENT_PREFIX}CORS_ALLOWED_HEADERS");
@@ -137,10 +618,10 @@ impl Config {
                 Some(
                     CorsConfig {
                         allowed_origin: origin,
-                        allowed_methods: methods.split(",")
+                        allowed_methods: methods.split(',')
                             .map(|val| HttpMethod::from_str(val.trim()).unwrap())
                             .collect::<Vec<HttpMethod>>(),
-                        allowed_headers: headers.split(",")
+                        allowed_headers: headers.split(',')
                             .map(|val| val.trim().to_string())
                             .collect::<Vec<String>>()
                     }
@@ -154,16 +635,374 @@ impl Config {
                 panic!("Missing configuration");
             }
         };
+        let telemetry = var(format!("{CONFIG_ENVIRONMENT_PREFIX}OTLP_ENDPOINT"))
+            .ok()
+            .map(|otlp_endpoint| TelemetryConfig {
+                otlp_endpoint,
+                service_name: var(format!("{CONFIG_ENVIRONMENT_PREFIX}OTLP_SERVICE_NAME"))
+                    .unwrap_or_else(|_| default_service_name()),
+            });
+
+        let mut access_log = AccessLogConfig::default();
+        if let Ok(enabled) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}ACCESS_LOG_ENABLED")) {
+            access_log.enabled = enabled.trim() == "true";
+        }
+        if let Ok(format) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}ACCESS_LOG_FORMAT")) {
+            access_log.format = AccessLogFormat::from_str(format.trim())?;
+        }
+        if let Ok(params) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}ACCESS_LOG_REDACT_QUERY_PARAMS")) {
+            access_log.redact_query_params = split_list(&params);
+        }
+        if let Ok(headers) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}ACCESS_LOG_HEADERS")) {
+            access_log.log_headers = split_list(&headers);
+        }
+        if let Ok(headers) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}ACCESS_LOG_REDACT_HEADERS")) {
+            access_log.redact_headers = split_list(&headers);
+        }
+
+        let mut reload = ReloadConfig::default();
+        if let Ok(secs) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}RELOAD_FETCH_TIMEOUT_SECS")) {
+            reload.fetch_timeout_secs = parse_secs(&secs)?;
+        }
+        if let Ok(secs) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}RELOAD_BACKOFF_INITIAL_SECS")) {
+            reload.backoff_initial_secs = parse_secs(&secs)?;
+        }
+        if let Ok(secs) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}RELOAD_BACKOFF_MAX_SECS")) {
+            reload.backoff_max_secs = parse_secs(&secs)?;
+        }
+
+        let mut health = HealthConfig::default();
+        if let Ok(path) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}HEALTH_LIVENESS_PATH")) {
+            health.liveness_path = path;
+        }
+        if let Ok(path) = var(format!("{CONFIG_ENVIRONMENT_PREFIX}HEALTH_READINESS_PATH")) {
+            health.readiness_path = path;
+        }
+
+        let admin = match var(format!("{CONFIG_ENVIRONMENT_PREFIX}ADMIN_TOKEN")) {
+            Ok(token) => Some(AdminConfig {
+                token,
+                port: match var(format!("{CONFIG_ENVIRONMENT_PREFIX}ADMIN_PORT")) {
+                    Ok(port) => Some(port.trim().parse().map_err(|_| ConfigError::InvalidPort(port))?),
+                    Err(_) => None,
+                },
+                path: var(format!("{CONFIG_ENVIRONMENT_PREFIX}ADMIN_PATH")).unwrap_or_else(|_| default_admin_path()),
+                persist: false,
+            }),
+            Err(_) => None,
+        };
+
         Ok(Config {
             reload_cron: std::env::var(format!("{}RELOAD_CRON", CONFIG_ENVIRONMENT_PREFIX)).unwrap_or("* * * * *".to_string()),
+            reload,
             openapi_urls: configs,
-            global_cors: config
+            openapi_directories: directories,
+            global_cors: config,
+            telemetry,
+            access_log,
+            health,
+            admin,
+            cache_dir: var(format!("{CONFIG_ENVIRONMENT_PREFIX}CACHE_DIR")).ok().map(PathBuf::from),
+            public_url: var(format!("{CONFIG_ENVIRONMENT_PREFIX}PUBLIC_URL")).ok(),
+            consumer_header: var(format!("{CONFIG_ENVIRONMENT_PREFIX}CONSUMER_HEADER")).ok(),
+            ui_dir: var(format!("{CONFIG_ENVIRONMENT_PREFIX}UI_DIR")).ok().map(PathBuf::from),
         })
     }
 }
 
+impl ConfigDiff {
+    /// Entries are compared with the running entries, as those may have been changed through the admin API
+    pub fn new(running: &Config, running_entries: &[OpenApiConfig], reloaded: &Config, reloaded_entries: &[OpenApiConfig]) -> ConfigDiff {
+        let mut restart_required = vec![];
+        if running.telemetry != reloaded.telemetry {
+            restart_required.push("telemetry");
+        }
+        if running.access_log != reloaded.access_log {
+            restart_required.push("access_log");
+        }
+        if running.health != reloaded.health {
+            restart_required.push("health");
+        }
+        if running.admin != reloaded.admin {
+            restart_required.push("admin");
+        }
+        if running.cache_dir != reloaded.cache_dir {
+            restart_required.push("cache_dir");
+        }
+
+        ConfigDiff {
+            added: reloaded_entries
+                .iter()
+                .filter(|config| !running_entries.iter().any(|running| running.name == config.name))
+                .cloned()
+                .collect(),
+            removed: running_entries
+                .iter()
+                .filter(|running| !reloaded_entries.iter().any(|config| config.name == running.name))
+                .map(|running| running.name.clone())
+                .collect(),
+            changed: reloaded_entries
+                .iter()
+                .filter(|config| running_entries.iter().any(|running| running.name == config.name && running != *config))
+                .cloned()
+                .collect(),
+            cors_changed: running.global_cors != reloaded.global_cors,
+            reload_cron_changed: running.reload_cron != reloaded.reload_cron,
+            reload_changed: running.reload != reloaded.reload,
+            public_url_changed: running.public_url != reloaded.public_url,
+            ui_dir_changed: running.ui_dir != reloaded.ui_dir,
+            restart_required,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == ConfigDiff::default()
+    }
+}
+
+fn parse_secs(value: &str) -> Result<u64, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::Invalid(format!("invalid number of seconds: {value}")))
+}
+
+/// Comma separated environment variable, empty when not set
+fn var_list(key: String) -> Vec<String> {
+    var(key).map(|value| split_list(&value)).unwrap_or_default()
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|val| val.trim().to_string())
+        .filter(|val| !val.is_empty())
+        .collect()
+}
+
 impl OpenApiConfig {
     pub fn uri(&self) -> Uri {
         Uri::try_from(&self.url).unwrap()
     }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.name.trim().is_empty() {
+            return Err(ConfigError::Invalid("name must not be empty".to_string()));
+        }
+        if let Some(slug) = &self.slug {
+            if slug.is_empty() || !slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+                return Err(ConfigError::Invalid(format!("slug may only contain a-z, 0-9, '-' and '_': {slug}")));
+            }
+        }
+
+        if let Some(upstream) = &self.upstream {
+            validate_upstream(upstream)?;
+        }
+        if let Some(reload_cron) = &self.reload_cron {
+            validate_cron(reload_cron)?;
+        }
+
+        if self.file_path().is_some() {
+            return match self.upstream {
+                Some(_) => Ok(()),
+                None => Err(ConfigError::Invalid(format!("upstream is required for {}", self.url))),
+            };
+        }
+
+        validate_upstream(&self.url).map_err(|_| ConfigError::InvalidUrl(self.url.clone()))
+    }
+
+    /// Configured slug or else the name in lowercase, with dashes for anything but letters and digits
+    pub fn slug(&self) -> String {
+        self.slug.clone().unwrap_or_else(|| slugify(&self.name))
+    }
+
+    /// Path of `file://` URLs, either absolute (`file:///specs/users.json`) or relative to the working directory
+    pub fn file_path(&self) -> Option<PathBuf> {
+        self.url.strip_prefix("file://").map(PathBuf::from)
+    }
+
+    /// Scheme and authority requests are proxied to, e.g. `https://petstore.swagger.io`
+    pub fn upstream(&self) -> String {
+        if let Some(upstream) = &self.upstream {
+            return upstream.trim_end_matches('/').to_string();
+        }
+
+        let uri = self.uri();
+
+        match uri.port_u16() {
+            Some(port) => format!("{}://{}:{}", uri.scheme_str().unwrap(), uri.host().unwrap(), port),
+            None => format!("{}://{}", uri.scheme_str().unwrap(), uri.host().unwrap()),
+        }
+    }
+}
+
+impl DirectoryConfig {
+    /// OpenAPI files in the directory, sorted by name so entries keep their order
+    pub fn files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&self.path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_openapi_file(path))
+            .collect();
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+/// `Users API v2` becomes `users-api-v2`
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        match c.is_ascii_alphanumeric() {
+            true => slug.push(c.to_ascii_lowercase()),
+            false if !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+            false => {}
+        }
+    }
+
+    match slug.trim_end_matches('-') {
+        "" => "entry".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+pub fn is_openapi_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|extension| extension.to_str()), Some("json") | Some("yaml") | Some("yml"))
+}
+
+/// `info.title` of an OpenAPI file, YAML is parsed as JSON is a subset of it
+fn openapi_title(path: &Path) -> Option<String> {
+    let contents = std::fs::read(path).ok()?;
+    let document: serde_yaml::Value = serde_yaml::from_slice(&contents).ok()?;
+
+    document.get("info")?.get("title")?.as_str().map(|title| title.to_string())
+}
+
+fn validate_cron(cron: &str) -> Result<(), ConfigError> {
+    // cron_parser panics on less than five fields
+    if cron.split_whitespace().count() != 5 || cron_parser::parse(cron, &Utc::now()).is_err() {
+        return Err(ConfigError::Invalid(format!("invalid reload_cron: {cron}")));
+    }
+
+    Ok(())
+}
+
+fn validate_upstream(upstream: &str) -> Result<(), ConfigError> {
+    match Uri::try_from(upstream) {
+        Ok(uri) if matches!(uri.scheme_str(), Some("http") | Some("https")) && uri.host().is_some() => Ok(()),
+        _ => Err(ConfigError::Invalid(format!("invalid upstream: {upstream}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{slugify, Config, ConfigDiff, DirectoryConfig, OpenApiConfig, TelemetryConfig};
+
+    const CONFIG: &str = r#"
+reload_cron = "* * * * *"
+
+[[openapi_urls]]
+name = "users"
+url = "http://users/openapi.json"
+
+[[openapi_urls]]
+name = "orders"
+url = "http://orders/openapi.json"
+"#;
+
+    #[test]
+    fn validate_config() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config.clone();
+        invalid.openapi_urls[1].name = "users".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = config.clone();
+        invalid.openapi_urls[1].slug = Some("users".to_string());
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = config.clone();
+        invalid.openapi_urls[1].slug = Some("Orders/v1".to_string());
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = config.clone();
+        invalid.openapi_urls[0].url = "users.json".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = config.clone();
+        invalid.health.readiness_path = "readyz".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = config.clone();
+        invalid.telemetry = Some(TelemetryConfig { otlp_endpoint: "collector:4318".to_string(), service_name: "gateway".to_string() });
+        assert!(invalid.validate().is_err());
+
+        let mut file = config;
+        file.openapi_urls[0].url = "file:///specs/users.json".to_string();
+        assert!(file.validate().is_err());
+        file.openapi_urls[0].upstream = Some("http://users:8080".to_string());
+        assert!(file.validate().is_ok());
+        assert_eq!("http://users:8080", file.openapi_urls[0].upstream());
+    }
+
+    #[test]
+    fn slug_from_name() {
+        assert_eq!("users-api-v2", slugify("Users API (v2)"));
+        assert_eq!("b-yaml", slugify("b.yaml"));
+        assert_eq!("entry", slugify("ユーザー"));
+
+        let config = OpenApiConfig { name: "Users".to_string(), slug: Some("people".to_string()), ..Default::default() };
+        assert_eq!("people", config.slug());
+    }
+
+    #[test]
+    fn entries_from_directory() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-directory-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.json"), r#"{"openapi": "3.0.0", "info": {"title": "Accounts"}, "paths": {}}"#).unwrap();
+        std::fs::write(dir.join("b.yaml"), "openapi: 3.0.0\ninfo:\n  title: users\npaths: {}\n").unwrap();
+        std::fs::write(dir.join("c.yml"), "openapi: 3.0.0\npaths: {}\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not an OpenAPI file").unwrap();
+
+        let mut config: Config = toml::from_str(CONFIG).unwrap();
+        config.openapi_directories.push(DirectoryConfig { path: dir.clone(), upstream: "http://internal".to_string(), optional: false });
+        assert!(config.validate().is_ok());
+
+        let entries = config.entry_configs();
+        let names: Vec<&str> = entries.iter().map(|config| config.name.as_str()).collect();
+
+        // The title of b.yaml is taken by an entry of openapi_urls
+        assert_eq!(vec!["users", "orders", "Accounts", "b.yaml", "c.yml"], names);
+        assert_eq!(format!("file://{}", dir.join("a.json").display()), entries[2].url);
+        assert_eq!("http://internal", entries[2].upstream());
+        assert_eq!(Some(dir.clone()), entries[2].directory);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diff_entries_against_running_entries() {
+        let running: Config = toml::from_str(CONFIG).unwrap();
+        let running_entries = vec![
+            running.openapi_urls[0].clone(),
+            running.openapi_urls[1].clone(),
+            // Persisted through the admin API, then removed from the file by hand
+            OpenApiConfig { name: "deleted".to_string(), url: "http://deleted/openapi.json".to_string(), ..Default::default() },
+        ];
+
+        let mut reloaded = running.clone();
+        reloaded.openapi_urls[0].url = "http://users/v2/openapi.json".to_string();
+        reloaded.openapi_urls.remove(1);
+        reloaded.openapi_urls.push(OpenApiConfig { name: "payments".to_string(), url: "http://payments/openapi.json".to_string(), ..Default::default() });
+        reloaded.access_log.enabled = true;
+
+        let diff = ConfigDiff::new(&running, &running_entries, &reloaded, &reloaded.openapi_urls);
+
+        assert_eq!(vec!["payments"], diff.added.iter().map(|config| config.name.as_str()).collect::<Vec<_>>());
+        assert_eq!(vec!["orders".to_string(), "deleted".to_string()], diff.removed);
+        assert_eq!(vec!["users"], diff.changed.iter().map(|config| config.name.as_str()).collect::<Vec<_>>());
+        assert!(!diff.cors_changed);
+        assert_eq!(vec!["access_log"], diff.restart_required);
+
+        assert!(ConfigDiff::new(&running, &running.openapi_urls, &running, &running.openapi_urls).is_empty());
+    }
 }
@@ -0,0 +1,361 @@
+use crate::config::{is_openapi_file, Config, ConfigDiff, OpenApiConfig};
+use crate::gateway::reload::HttpReloader;
+use crate::{RwConfig, RwGatewayEntries};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+/// Editors emit several events for a single save
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    ConfigFile,
+    Directory,
+}
+
+/// Reloads the config file when it changes on disk or when SIGHUP is received, and keeps the
+/// entries of `openapi_directories` in sync with the files in those directories
+pub fn spawn_config_watcher(path: Option<PathBuf>, config: RwConfig, entries: RwGatewayEntries, reloader: Arc<HttpReloader>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    let file_name = path.as_ref().and_then(|path| path.file_name()).map(|name| name.to_os_string());
+    let watch_sender = sender.clone();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            if !(event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()) {
+                return;
+            }
+
+            if event.paths.iter().any(|path| path.file_name().is_some() && path.file_name() == file_name.as_deref()) {
+                let _ = watch_sender.send(Change::ConfigFile);
+            } else if event.paths.iter().any(|path| is_openapi_file(path)) {
+                let _ = watch_sender.send(Change::Directory);
+            }
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => Some(watcher),
+        Err(err) => {
+            tracing::warn!("Watching for file changes failed, reload with SIGHUP instead: {err}");
+            None
+        }
+    };
+
+    // Watch the directory, as editors often replace the file instead of writing to it
+    let mut watched = HashSet::new();
+    if let Some(path) = &path {
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        if watch(&mut watcher, &mut watched, &directory) {
+            tracing::info!("Watching config file: {}", path.display());
+        }
+
+        #[cfg(unix)]
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangup = signal(SignalKind::hangup()).unwrap();
+            while hangup.recv().await.is_some() {
+                tracing::info!("Received SIGHUP, reloading config");
+                let _ = sender.send(Change::ConfigFile);
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        watch_directories(&mut watcher, &mut watched, &config).await;
+
+        while let Some(change) = receiver.recv().await {
+            sleep(DEBOUNCE).await;
+            let mut changes = vec![change];
+            while let Ok(change) = receiver.try_recv() {
+                changes.push(change);
+            }
+
+            if let (Some(path), true) = (&path, changes.contains(&Change::ConfigFile)) {
+                reload_config(path, &config, &entries, &reloader).await;
+                // Directories may have been added to the config file
+                watch_directories(&mut watcher, &mut watched, &config).await;
+            }
+            if changes.contains(&Change::Directory) {
+                sync_directories(&config, &entries, &reloader).await;
+            }
+        }
+    });
+}
+
+async fn watch_directories(watcher: &mut Option<RecommendedWatcher>, watched: &mut HashSet<PathBuf>, config: &RwConfig) {
+    for directory in &config.read().await.openapi_directories {
+        if watch(watcher, watched, &directory.path) {
+            tracing::info!("Watching directory: {}", directory.path.display());
+        }
+    }
+}
+
+fn watch(watcher: &mut Option<RecommendedWatcher>, watched: &mut HashSet<PathBuf>, directory: &Path) -> bool {
+    let Some(watcher) = watcher else {
+        return false;
+    };
+    if watched.contains(directory) {
+        return false;
+    }
+
+    match watcher.watch(directory, RecursiveMode::NonRecursive) {
+        Ok(_) => watched.insert(directory.to_path_buf()),
+        Err(err) => {
+            tracing::warn!("Watching {} failed: {err}", directory.display());
+            false
+        }
+    }
+}
+
+/// Applies the config file to the running gateway, an invalid file keeps the running config
+pub async fn reload_config(path: &Path, config: &RwConfig, entries: &RwGatewayEntries, reloader: &HttpReloader) {
+    let reloaded = match Config::parse_from_file(path).and_then(|reloaded| reloaded.validate().map(|_| reloaded)) {
+        Ok(reloaded) => reloaded,
+        Err(err) => {
+            tracing::error!("Keeping running config, loading {} failed: {err}", path.display());
+            return;
+        }
+    };
+
+    let reloaded_entries = reloaded.entry_configs();
+    let running_entries: Vec<OpenApiConfig> = entries.read().await
+        .iter()
+        .map(|entry| entry.config.clone())
+        .collect();
+    let diff = ConfigDiff::new(&*config.read().await, &running_entries, &reloaded, &reloaded_entries);
+
+    if diff.is_empty() {
+        tracing::info!("Config reloaded without changes");
+        return;
+    }
+
+    tracing::info!(
+        "Config reloaded, added: {:?}, removed: {:?}, changed: {:?}, CORS changed: {}, reload_cron changed: {}, reload changed: {}, public_url changed: {}, ui_dir changed: {}",
+        diff.added.iter().map(|config| &config.name).collect::<Vec<_>>(),
+        diff.removed,
+        diff.changed.iter().map(|config| &config.name).collect::<Vec<_>>(),
+        diff.cors_changed,
+        diff.reload_cron_changed,
+        diff.reload_changed,
+        diff.public_url_changed,
+        diff.ui_dir_changed
+    );
+    if !diff.restart_required.is_empty() {
+        tracing::warn!("Changes to {:?} take effect after a restart", diff.restart_required);
+    }
+
+    // Fetch before swapping, requests are served by the running entries meanwhile
+    let to_fetch: Vec<OpenApiConfig> = diff.added.iter().chain(diff.changed.iter()).cloned().collect();
+    let mut fetched = reloader.load(&to_fetch, &reloaded.reload).await;
+
+    {
+        let mut entries = entries.write().await;
+        let mut running = std::mem::take(&mut *entries);
+
+        *entries = reloaded_entries
+            .iter()
+            .filter_map(|openapi_config| {
+                let fetched_index = fetched.iter().position(|entry| entry.config.name == openapi_config.name);
+                let running_index = running.iter().position(|entry| entry.config.name == openapi_config.name);
+
+                match (fetched_index, running_index) {
+                    // A failing fetch does not throw away a working entry, the next config reload tries the changed config again
+                    (Some(fetched_index), Some(running_index))
+                        if fetched[fetched_index].status.consecutive_failures > 0
+                            && running[running_index].is_loaded()
+                            && running[running_index].config.url == openapi_config.url =>
+                    {
+                        tracing::warn!("Keeping running entry {}, fetching it with the changed config failed", openapi_config.name);
+                        Some(running.remove(running_index))
+                    }
+                    (Some(fetched_index), _) => Some(fetched.remove(fetched_index)),
+                    (None, running_index) => running_index.map(|index| running.remove(index)),
+                }
+            })
+            .collect();
+    }
+
+    *config.write().await = reloaded;
+}
+
+/// Adds and removes entries for files added to or removed from `openapi_directories`, and fetches
+/// the files of the other directory entries again, other entries are left untouched
+pub async fn sync_directories(config: &RwConfig, entries: &RwGatewayEntries, reloader: &HttpReloader) {
+    let config = config.read().await.clone();
+    let files: Vec<OpenApiConfig> = config.entry_configs()
+        .into_iter()
+        .filter(|file| file.directory.is_some())
+        .collect();
+
+    let (added, removed): (Vec<OpenApiConfig>, Vec<String>) = {
+        let entries = entries.read().await;
+        // Changed files are removed and added again
+        let removed: Vec<String> = entries.iter()
+            .filter(|entry| entry.config.directory.is_some() && !files.contains(&entry.config))
+            .map(|entry| entry.config.name.clone())
+            .collect();
+        let added = files.iter()
+            .filter(|file| !entries.iter().any(|entry| entry.config.name == file.name && !removed.contains(&entry.config.name)))
+            .cloned()
+            .collect();
+
+        (added, removed)
+    };
+
+    if !added.is_empty() || !removed.is_empty() {
+        tracing::info!(
+            "Directories changed, added: {:?}, removed: {:?}",
+            added.iter().map(|config| &config.name).collect::<Vec<_>>(),
+            removed
+        );
+    }
+
+    let fetched = reloader.load(&added, &config.reload).await;
+    {
+        let mut entries = entries.write().await;
+        entries.retain(|entry| !removed.contains(&entry.config.name));
+        entries.extend(fetched);
+    }
+
+    let added: Vec<String> = added.into_iter().map(|config| config.name).collect();
+    reloader
+        .reload_where(entries, &config.reload, |entry| entry.config.directory.is_some() && !added.contains(&entry.config.name))
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::watch::{reload_config, sync_directories};
+    use crate::config::{Config, DirectoryConfig, OpenApiConfig};
+    use crate::gateway::{EntryStatus, GatewayEntry, OpenApiFile};
+    use crate::gateway::reload::{HttpFetcher, HttpReloader, Reloader, SystemClock};
+    use crate::web::HttpClient;
+    use crate::{RwConfig, RwGatewayEntries};
+    use chrono::Utc;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const CONFIG: &str = r#"
+reload_cron = "* * * * *"
+
+[[openapi_urls]]
+name = "users"
+url = "http://users/openapi.json"
+"#;
+
+    #[tokio::test]
+    async fn invalid_config_keeps_running_config() {
+        let path = write_config("invalid", "reload_cron = \"not a cron\"\nopenapi_urls = []\n");
+        let (config, entries) = running();
+
+        reload_config(&path, &config, &entries, &reloader()).await;
+
+        assert_eq!(1, entries.read().await.len());
+        assert_eq!("* * * * *", config.read().await.reload_cron);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_entries_and_apply_cors() {
+        let path = write_config(
+            "removed",
+            "reload_cron = \"0 * * * *\"\nopenapi_urls = []\n\n[global_cors]\nallowed_origin = \"*\"\nallowed_methods = [\"GET\"]\nallowed_headers = []\n",
+        );
+        let (config, entries) = running();
+
+        reload_config(&path, &config, &entries, &reloader()).await;
+
+        assert!(entries.read().await.is_empty());
+        assert_eq!("0 * * * *", config.read().await.reload_cron);
+        assert_eq!("*", config.read().await.global_cors.as_ref().unwrap().allowed_origin);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failing_fetch_keeps_running_entry() {
+        let url = "http://127.0.0.1:1/openapi.json";
+        let path = write_config("failing", &format!("reload_cron = \"* * * * *\"\n\n[[openapi_urls]]\nname = \"users\"\nurl = \"{url}\"\ninclude_tags = [\"public\"]\n"));
+        let (config, entries) = running();
+        {
+            let mut entries = entries.write().await;
+            entries[0].config.url = url.to_string();
+            entries[0].openapi_file = Some(OpenApiFile {
+                content_type: "application/json".to_string(),
+                contents: br#"{"openapi": "3.0.0", "paths": {}}"#.to_vec(),
+                content_hash: "hash".to_string(),
+                etag: None,
+                last_modified: None,
+                base_paths: vec![String::new()],
+                path_base_paths: Default::default(),
+                diagnostics: vec![],
+            });
+        }
+
+        reload_config(&path, &config, &entries, &reloader()).await;
+
+        let entries = entries.read().await;
+        assert!(entries[0].is_loaded());
+        assert!(entries[0].config.include_tags.is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_entries_with_directory() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-watch-directory-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("orders.json"), r#"{"openapi": "3.0.0", "paths": {"/orders": {"get": {}}}}"#).unwrap();
+        let (config, entries) = running();
+        config.write().await.openapi_directories.push(DirectoryConfig { path: dir.clone(), upstream: "http://orders".to_string(), optional: false });
+
+        sync_directories(&config, &entries, &reloader()).await;
+        {
+            let entries = entries.read().await;
+            assert_eq!(vec!["users", "orders.json"], entries.iter().map(|entry| entry.config.name.as_str()).collect::<Vec<_>>());
+            assert_eq!(1, entries[1].routes.len());
+        }
+
+        std::fs::write(dir.join("orders.json"), r#"{"openapi": "3.0.0", "paths": {"/orders": {"get": {}, "post": {}}}}"#).unwrap();
+        sync_directories(&config, &entries, &reloader()).await;
+        assert_eq!(2, entries.read().await[1].routes.len());
+
+        std::fs::remove_file(dir.join("orders.json")).unwrap();
+        sync_directories(&config, &entries, &reloader()).await;
+        assert_eq!(1, entries.read().await.len());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn running() -> (RwConfig, RwGatewayEntries) {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let entry = GatewayEntry {
+            config: OpenApiConfig {
+                name: "users".to_string(),
+                url: "http://users/openapi.json".to_string(),
+                ..Default::default()
+            },
+            openapi_file: None,
+            routes: vec![],
+            status: EntryStatus::succeeded(Utc::now()),
+            changes: Default::default(),
+        };
+
+        (Arc::new(RwLock::new(config)), Arc::new(RwLock::new(vec![entry])))
+    }
+
+    fn reloader() -> HttpReloader {
+        Reloader::new(HttpFetcher::new(HttpClient::new()), SystemClock, None)
+    }
+
+    fn write_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("openapi-gateway-watch-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+
+        path
+    }
+}
@@ -1,30 +1,174 @@
+pub mod bundle;
+pub mod cache;
+pub mod diff;
+pub mod filter;
+pub mod lint;
+pub mod merge;
 pub mod openapi;
+pub mod reload;
 
 use crate::config::OpenApiConfig;
+use crate::gateway::diff::{diff, ChangeLog, PendingUpdate, SpecChanges};
+use crate::gateway::lint::Diagnostic;
+use crate::gateway::openapi::set_servers;
+use crate::openapi::OpenApiV3;
+use chrono::{DateTime, Duration, Utc};
 use regex::Regex;
 use crate::openapi::Parameter;
+use std::collections::BTreeMap;
 
 #[derive(Debug)]
 pub struct GatewayEntry {
     pub config: OpenApiConfig,
     pub openapi_file: Option<OpenApiFile>,
     pub routes: Vec<Route>,
+    pub status: EntryStatus,
+    pub changes: ChangeLog,
+}
+
+/// Outcome of fetching the OpenAPI file of an entry
+#[derive(Debug, Clone, Default)]
+pub struct EntryStatus {
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    /// First failure since the last success, the OpenAPI file is stale from then on
+    pub failing_since: Option<DateTime<Utc>>,
+    /// Failing entries are not fetched again before this time
+    pub retry_after: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug)]
 pub struct OpenApiFile {
     pub content_type: String,
-    pub contents: Vec<u8>
+    pub contents: Vec<u8>,
+    /// SHA-256 of the fetched file, hex encoded
+    pub content_hash: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Paths of the servers the file was published with, routes are registered under these
+    pub base_paths: Vec<String>,
+    /// Paths of the servers of path items, these replace `base_paths` for their path
+    pub path_base_paths: BTreeMap<String, Vec<String>>,
+    /// Problems found in the file, it is served and routed nonetheless
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Identifies a fetched OpenAPI file, used to skip downloading and parsing an unchanged file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileVersion {
+    pub content_hash: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Route {
     pub uri_regex: Regex,
+    /// Path as written in the OpenAPI file, including the server prefix
+    pub template: String,
     pub method: String,
-    pub path_parameters: Vec<Parameter>
+    pub operation_id: Option<String>,
+    pub path_parameters: Vec<Parameter>,
+    pub deprecated: bool,
+    /// `x-sunset` of a deprecated operation
+    pub sunset: Option<String>,
 }
 
 impl GatewayEntry {
+    /// Entry without OpenAPI file, used when the first fetch fails
+    pub fn unloaded(config: OpenApiConfig) -> GatewayEntry {
+        GatewayEntry {
+            config,
+            openapi_file: None,
+            routes: vec![],
+            status: EntryStatus::default(),
+            changes: ChangeLog::default(),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.openapi_file.is_some()
+    }
+
+    /// Serving the last known good OpenAPI file while fetching it fails
+    pub fn is_stale(&self) -> bool {
+        self.is_loaded() && self.status.consecutive_failures > 0
+    }
+
+    /// Routes matching one of the reserved paths, which are routed before proxied routes
+    pub fn shadowed_routes<'a>(&'a self, reserved_paths: &'a [String]) -> Vec<(&'a Route, &'a str)> {
+        self.routes.iter()
+            .filter_map(|route| reserved_paths.iter().find(|path| route.uri_regex.is_match(path)).map(|path| (route, path.as_str())))
+            .collect()
+    }
+
+    pub fn warn_on_shadowed_routes(&self, reserved_paths: &[String]) {
+        for (route, path) in self.shadowed_routes(reserved_paths) {
+            tracing::warn!("Route {} {} of {} is shadowed by the health endpoint {path}", route.method, route.uri_regex.as_str(), self.config.name);
+        }
+    }
+
+    /// Replaces the OpenAPI file with a newly fetched one, breaking changes are held back when the entry blocks them
+    pub fn update(&mut self, mut updated: GatewayEntry, now: DateTime<Utc>) {
+        let old_document = self.openapi_file.as_ref().and_then(OpenApiFile::document);
+        let new_document = updated.openapi_file.as_ref().and_then(OpenApiFile::document);
+        let changes = match (old_document, new_document) {
+            (Some(old), Some(new)) => diff(&old, &new),
+            _ => vec![],
+        };
+
+        let mut log = std::mem::take(&mut self.changes);
+        let pending = log.pending.take();
+        if !changes.is_empty() {
+            let changes = SpecChanges::new(
+                now,
+                self.openapi_file.as_ref().map(|file| file.content_hash.clone()).unwrap_or_default(),
+                updated.openapi_file.as_ref().map(|file| file.content_hash.clone()).unwrap_or_default(),
+                changes,
+            );
+
+            if changes.breaking && self.config.block_breaking_changes {
+                // Only log once for every new version that is held back
+                if pending.map(|pending| pending.changes.to_hash != changes.to_hash).unwrap_or(true) {
+                    tracing::warn!("Holding back breaking changes to {} until acknowledged: {changes}", self.config.name);
+                }
+                self.status = updated.status.clone();
+                log.pending = Some(PendingUpdate { changes, entry: Box::new(updated) });
+                self.changes = log;
+                return;
+            }
+
+            if changes.breaking {
+                tracing::warn!("OpenAPI file of {} changed with breaking changes: {changes}", self.config.name);
+            } else {
+                tracing::info!("OpenAPI file of {} changed: {changes}", self.config.name);
+            }
+            log.push(changes);
+        }
+
+        updated.changes = log;
+        *self = updated;
+    }
+
+    /// Applies the breaking changes held back, false when there are none
+    pub fn acknowledge(&mut self) -> bool {
+        let Some(pending) = self.changes.pending.take() else {
+            return false;
+        };
+
+        let mut log = std::mem::take(&mut self.changes);
+        log.push(pending.changes);
+
+        let mut entry = *pending.entry;
+        entry.status = self.status.clone();
+        entry.changes = log;
+        *self = entry;
+
+        true
+    }
+
     pub fn contains_route_and_method(&self, path: &str, method: &str) -> bool {
         self.routes
             .iter()
@@ -43,9 +187,70 @@ impl GatewayEntry {
     }
 }
 
+impl EntryStatus {
+    pub fn succeeded(now: DateTime<Utc>) -> EntryStatus {
+        EntryStatus {
+            last_fetch: Some(now),
+            last_success: Some(now),
+            ..Default::default()
+        }
+    }
+
+    /// Keeps the last success, so it is known how old the current OpenAPI file is
+    pub fn record_failure(&mut self, error: String, now: DateTime<Utc>, backoff: Duration) {
+        self.last_fetch = Some(now);
+        self.last_error = Some(error);
+        self.consecutive_failures += 1;
+        self.failing_since.get_or_insert(now);
+        self.retry_after = Some(now + backoff);
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.retry_after.map(|retry_after| retry_after <= now).unwrap_or(true)
+    }
+}
+
+impl OpenApiFile {
+    /// Contents are stored as JSON, also for YAML files
+    pub fn document(&self) -> Option<OpenApiV3> {
+        serde_json::from_slice(&self.contents).ok()
+    }
+
+    /// Contents with the gateway at `public_url` as server, e.g. `https://gateway.example.com`
+    pub fn published(&self, public_url: &str) -> Vec<u8> {
+        match serde_json::from_slice::<serde_json::Value>(&self.contents) {
+            Ok(mut document) => {
+                set_servers(&mut document, public_url, &self.base_paths, &self.path_base_paths);
+                serde_json::to_vec(&document).unwrap()
+            }
+            Err(_) => self.contents.clone(),
+        }
+    }
+
+    /// Base paths a path of the file is routed and published under
+    pub fn base_paths_of(&self, path: &str) -> &[String] {
+        self.path_base_paths.get(path).unwrap_or(&self.base_paths)
+    }
+
+    pub fn version(&self) -> FileVersion {
+        FileVersion {
+            content_hash: self.content_hash.clone(),
+            etag: self.etag.clone(),
+            last_modified: self.last_modified.clone(),
+        }
+    }
+
+    /// Keeps the contents, only the validators of an unchanged file may differ
+    pub fn set_version(&mut self, version: FileVersion) {
+        self.etag = version.etag;
+        self.last_modified = version.last_modified;
+    }
+}
+
 impl Route {
+    #[cfg(test)]
     fn new(uri_regex: Regex, method: String) -> Route {
-        Self { uri_regex, method, path_parameters: vec![] }
+        Self { template: uri_regex.to_string(), uri_regex, method, operation_id: None, path_parameters: vec![], deprecated: false, sunset: None }
     }
 }
 #[cfg(test)]
@@ -53,7 +258,7 @@ mod tests {
     use std::str::FromStr;
     use regex::Regex;
     use crate::config::OpenApiConfig;
-    use crate::gateway::{GatewayEntry, OpenApiFile, Route};
+    use crate::gateway::{EntryStatus, GatewayEntry, OpenApiFile, Route};
 
     #[test]
     fn test_no_match_on_method() {
@@ -80,16 +285,39 @@ mod tests {
         assert!(entry.contains_route_and_method("/test", "get"))
     }
 
+    #[test]
+    fn routes_shadowed_by_reserved_paths() {
+        let entry = entry_with_route(
+            vec![
+                Route::new(Regex::from_str("^/healthz$").unwrap(),"get".to_string()),
+                Route::new(Regex::from_str("^/users$").unwrap(),"get".to_string()),
+            ]
+        );
+        let reserved = vec!["/healthz".to_string(), "/readyz".to_string()];
+
+        let shadowed = entry.shadowed_routes(&reserved);
+        assert_eq!(vec!["^/healthz$"], shadowed.iter().map(|(route, _)| route.uri_regex.as_str()).collect::<Vec<_>>());
+        assert_eq!("/healthz", shadowed[0].1);
+    }
+
     fn entry_with_route(routes: Vec<Route>) -> GatewayEntry {
         GatewayEntry {
-            config: OpenApiConfig { name: "".to_string(), url: "".to_string() },
+            config: OpenApiConfig::default(),
             openapi_file: Some(
                 OpenApiFile {
                     content_type: "".to_string(),
-                    contents: vec![]
+                    contents: vec![],
+                    content_hash: "".to_string(),
+                    etag: None,
+                    last_modified: None,
+                    base_paths: vec![String::new()],
+                    path_base_paths: Default::default(),
+                    diagnostics: vec![],
                 }
             ),
-            routes
+            routes,
+            status: EntryStatus::default(),
+            changes: Default::default(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,425 @@
+use crate::gateway::filter::COMPONENT_SECTIONS;
+use crate::web::{simple_get, HttpClient, HttpError};
+use axum::http::{HeaderMap, Uri};
+use serde_json::{json, Value};
+use serde_yaml::Value as YmlValue;
+use std::collections::{HashMap, HashSet};
+
+#[derive(thiserror::Error, Debug)]
+pub enum BundleError {
+    #[error("fetching {0} failed: {1}")]
+    Http(String, HttpError),
+    #[error("reading {0} failed: {1}")]
+    IO(String, std::io::Error),
+    #[error("parsing {0} failed: {1}")]
+    Parse(String, String),
+    #[error("$ref not found: {0}")]
+    NotFound(String),
+    #[error("circular $ref to a path item: {0}")]
+    Cycle(String),
+    #[error("$ref to {0} uses another scheme than the OpenAPI file")]
+    Scheme(String),
+}
+
+/// Whether the document has a `$ref` to another file
+pub fn has_external_refs(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => object.iter().any(|(key, value)| match (key.as_str(), value) {
+            ("$ref", Value::String(reference)) => !reference.starts_with('#'),
+            (_, value) => has_external_refs(value),
+        }),
+        Value::Array(values) => values.iter().any(has_external_refs),
+        _ => false,
+    }
+}
+
+/// Replaces `$ref`s to other files, relative to `url` or absolute, with local `$ref`s to copies of their
+/// targets in the components of the document. Targets are fetched like the OpenAPI file itself.
+pub async fn bundle(client: &HttpClient, url: &str, mut document: Value) -> Result<Value, BundleError> {
+    let documents = load_documents(client, url, &document).await?;
+
+    let mut bundler = Bundler {
+        root: url.to_string(),
+        swagger: document.get("swagger").is_some(),
+        documents,
+        names: HashMap::new(),
+        taken: HashSet::new(),
+        bundled: vec![],
+        inlining: vec![],
+    };
+    // Names already in use by the document itself
+    for container in bundler.containers() {
+        let names = document.pointer(&container).and_then(Value::as_object).into_iter().flatten();
+        bundler.taken.extend(names.map(|(name, _)| format!("{container}/{name}")));
+    }
+
+    bundler.rewrite(&mut document, url, &mut vec![])?;
+
+    for (container, name, value) in bundler.bundled {
+        insert(&mut document, &container, name, value)
+            .ok_or_else(|| BundleError::Parse(url.to_string(), format!("{container} is not an object")))?;
+    }
+
+    Ok(document)
+}
+
+/// Adds a value to the object at the pointer, creating the objects on the way
+fn insert(document: &mut Value, container: &str, name: String, value: Value) -> Option<()> {
+    let mut target = document;
+    for key in container.trim_start_matches('/').split('/') {
+        target = target.as_object_mut()?.entry(key).or_insert_with(|| json!({}));
+    }
+    target.as_object_mut()?.insert(name, value);
+
+    Some(())
+}
+
+/// Fetches every file referenced from the document and the files referenced from those
+async fn load_documents(client: &HttpClient, url: &str, document: &Value) -> Result<HashMap<String, Value>, BundleError> {
+    let mut documents = HashMap::new();
+    let mut pending: Vec<String> = external_files(document, url);
+
+    while let Some(file) = pending.pop() {
+        if file == url || documents.contains_key(&file) {
+            continue;
+        }
+        // Files on the gateway's own disk are only read for OpenAPI files loaded from disk
+        if scheme(&file) != scheme(url) {
+            return Err(BundleError::Scheme(file));
+        }
+
+        let loaded = load(client, &file).await?;
+        pending.extend(external_files(&loaded, &file));
+        documents.insert(file, loaded);
+    }
+
+    Ok(documents)
+}
+
+async fn load(client: &HttpClient, url: &str) -> Result<Value, BundleError> {
+    tracing::info!("Fetching $ref: {url}");
+    let contents = match url.strip_prefix("file://") {
+        Some(path) => tokio::fs::read(path).await.map_err(|err| BundleError::IO(url.to_string(), err))?,
+        None => {
+            let uri = Uri::try_from(url).map_err(|err| BundleError::Parse(url.to_string(), err.to_string()))?;
+            let (status, _, body) = simple_get(client, &uri, HeaderMap::new())
+                .await
+                .map_err(|err| BundleError::Http(url.to_string(), err))?;
+            if !status.is_success() {
+                return Err(BundleError::Http(url.to_string(), HttpError::Status(status)));
+            }
+
+            body.to_vec()
+        }
+    };
+
+    // JSON is a subset of YAML
+    serde_yaml::from_slice::<YmlValue>(&contents)
+        .map_err(|err| err.to_string())
+        .and_then(|value| serde_json::to_value(value).map_err(|err| err.to_string()))
+        .map_err(|err| BundleError::Parse(url.to_string(), err))
+}
+
+fn scheme(url: &str) -> Option<&str> {
+    url.split_once("://").map(|(scheme, _)| scheme)
+}
+
+fn external_files(value: &Value, base: &str) -> Vec<String> {
+    let mut files = vec![];
+    collect_files(value, base, &mut files);
+
+    files
+}
+
+fn collect_files(value: &Value, base: &str, files: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) if !reference.starts_with('#') => {
+                        files.push(split_ref(base, reference).0);
+                    }
+                    (_, value) => collect_files(value, base, files),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_files(value, base, files)),
+        _ => {}
+    }
+}
+
+struct Bundler {
+    root: String,
+    swagger: bool,
+    documents: HashMap<String, Value>,
+    /// Local pointer per bundled target, keyed by file and pointer
+    names: HashMap<(String, String), String>,
+    taken: HashSet<String>,
+    bundled: Vec<(String, String, Value)>,
+    /// Path items being inlined, to detect cycles
+    inlining: Vec<(String, String)>,
+}
+
+impl Bundler {
+    fn containers(&self) -> Vec<String> {
+        if self.swagger {
+            ["definitions", "parameters", "responses"].iter().map(|container| format!("/{container}")).collect()
+        } else {
+            COMPONENT_SECTIONS.iter().map(|section| format!("/components/{section}")).collect()
+        }
+    }
+
+    /// `path` are the keys from the root of the document to `value`, used to tell what a `$ref` points to
+    fn rewrite(&mut self, value: &mut Value, base: &str, path: &mut Vec<String>) -> Result<(), BundleError> {
+        match value {
+            Value::Object(object) => {
+                if let Some(Value::String(reference)) = object.get("$ref") {
+                    let reference = reference.clone();
+                    // Keys next to a `$ref` are allowed by OpenAPI 3.1 and JSON Schema 2020-12
+                    for (key, value) in object.iter_mut().filter(|(key, _)| *key != "$ref") {
+                        path.push(key.clone());
+                        self.rewrite(value, base, path)?;
+                        path.pop();
+                    }
+
+                    return self.rewrite_ref(value, &reference, base, path);
+                }
+
+                for (key, value) in object.iter_mut() {
+                    path.push(key.clone());
+                    self.rewrite(value, base, path)?;
+                    path.pop();
+                }
+            }
+            Value::Array(values) => {
+                for (index, value) in values.iter_mut().enumerate() {
+                    path.push(index.to_string());
+                    self.rewrite(value, base, path)?;
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn rewrite_ref(&mut self, value: &mut Value, reference: &str, base: &str, path: &mut Vec<String>) -> Result<(), BundleError> {
+        if base == self.root && reference.starts_with('#') {
+            return Ok(());
+        }
+
+        let target = split_ref(base, reference);
+        let local = if target.0 == self.root {
+            target.1
+        } else {
+            match self.container(path) {
+                Some(container) => self.bundle_target(target, container)?,
+                None => return self.inline_target(value, target, path),
+            }
+        };
+        value["$ref"] = json!(format!("#{local}"));
+
+        Ok(())
+    }
+
+    /// Copies the target into the container once, later `$ref`s to it point to the copy
+    fn bundle_target(&mut self, target: (String, String), container: String) -> Result<String, BundleError> {
+        if let Some(pointer) = self.names.get(&target) {
+            return Ok(pointer.clone());
+        }
+
+        let name = self.name(&target, &container);
+        let pointer = format!("{container}/{name}");
+        self.taken.insert(pointer.clone());
+        // Registered before rewriting the target, so it can refer to itself
+        self.names.insert(target.clone(), pointer.clone());
+
+        let mut value = self.target(&target)?;
+        let mut path: Vec<String> = pointer.trim_start_matches('/').split('/').map(str::to_string).collect();
+        self.rewrite(&mut value, &target.0, &mut path)?;
+        self.bundled.push((container, name, value));
+
+        Ok(pointer)
+    }
+
+    /// Path items can not be referenced from components in OpenAPI 3.0, so they are copied in place.
+    /// Keys next to the `$ref` win.
+    fn inline_target(&mut self, value: &mut Value, target: (String, String), path: &mut Vec<String>) -> Result<(), BundleError> {
+        if self.inlining.contains(&target) {
+            return Err(BundleError::Cycle(format!("{}#{}", target.0, target.1)));
+        }
+
+        let mut inlined = self.target(&target)?;
+        self.inlining.push(target.clone());
+        self.rewrite(&mut inlined, &target.0, path)?;
+        self.inlining.pop();
+
+        let siblings = std::mem::replace(value, inlined);
+        if let (Value::Object(siblings), Some(inlined)) = (siblings, value.as_object_mut()) {
+            inlined.extend(siblings.into_iter().filter(|(key, _)| key != "$ref"));
+        }
+
+        Ok(())
+    }
+
+    fn target(&self, (file, pointer): &(String, String)) -> Result<Value, BundleError> {
+        self.documents.get(file)
+            .and_then(|document| document.pointer(pointer))
+            .cloned()
+            .ok_or_else(|| BundleError::NotFound(format!("{file}#{pointer}")))
+    }
+
+    /// Section a `$ref` at `path` belongs in, `None` for path items
+    fn container(&self, path: &[String]) -> Option<String> {
+        let parent = path.len().checked_sub(2).map(|index| path[index].as_str());
+        let last = path.last().map(String::as_str);
+
+        if path.len() == 2 && path[0] == "paths" {
+            return None;
+        }
+
+        let section = match (parent, last) {
+            (Some("parameters"), _) => "parameters",
+            (Some("responses"), _) => "responses",
+            _ if self.swagger => "definitions",
+            (Some("webhooks" | "pathItems"), _) => "pathItems",
+            (_, Some("requestBody")) => "requestBodies",
+            (Some(section @ ("headers" | "examples" | "links" | "callbacks")), _) => section,
+            _ => "schemas",
+        };
+
+        Some(match self.swagger {
+            true => format!("/{section}"),
+            false => format!("/components/{section}"),
+        })
+    }
+
+    /// Last segment of the pointer or else the file name, made unique within the container
+    fn name(&self, (file, pointer): &(String, String), container: &str) -> String {
+        let segment = pointer.rsplit('/').next().filter(|segment| !segment.is_empty()).unwrap_or_else(|| {
+            let file_name = file.rsplit('/').next().unwrap_or(file);
+            file_name.split('.').next().unwrap_or(file_name)
+        });
+        // Component names may only contain `a-z A-Z 0-9 . - _`
+        let name: String = segment.replace("~1", "_").replace("~0", "_")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+
+        let mut unique = name.clone();
+        let mut count = 1;
+        while self.taken.contains(&format!("{container}/{unique}")) {
+            count += 1;
+            unique = format!("{name}_{count}");
+        }
+
+        unique
+    }
+}
+
+/// Absolute URL of the file and the pointer into it of a `$ref` found in `base`
+fn split_ref(base: &str, reference: &str) -> (String, String) {
+    let (file, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+    let file = if file.is_empty() { base.to_string() } else { resolve(base, file) };
+
+    (file, pointer.to_string())
+}
+
+/// Resolves a relative reference against the URL of the file it was found in
+fn resolve(base: &str, reference: &str) -> String {
+    if reference.contains("://") {
+        return reference.to_string();
+    }
+
+    let (origin, base_path) = match base.split_once("://") {
+        Some((scheme, rest)) => {
+            let index = rest.find('/').unwrap_or(rest.len());
+            (format!("{scheme}://{}", &rest[..index]), &rest[index..])
+        }
+        None => (String::new(), base),
+    };
+
+    let mut segments: Vec<&str> = if reference.starts_with('/') {
+        vec![]
+    } else {
+        let directory = base_path.rsplit_once('/').map(|(directory, _)| directory).unwrap_or("");
+        directory.split('/').filter(|segment| !segment.is_empty()).collect()
+    };
+    for segment in reference.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    format!("{origin}/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gateway::bundle::{bundle, has_external_refs, resolve, BundleError};
+    use crate::web::HttpClient;
+    use serde_json::json;
+
+    #[test]
+    fn resolve_relative_refs() {
+        assert_eq!("https://users/specs/schemas/user.yaml", resolve("https://users/specs/openapi.yaml", "./schemas/user.yaml"));
+        assert_eq!("https://users/common.yaml", resolve("https://users/specs/openapi.yaml", "../common.yaml"));
+        assert_eq!("https://users/common.yaml", resolve("https://users/specs/openapi.yaml", "/common.yaml"));
+        assert_eq!("file:///specs/user.yaml", resolve("file:///specs/openapi.yaml", "user.yaml"));
+        assert_eq!("https://other/user.yaml", resolve("file:///specs/openapi.yaml", "https://other/user.yaml"));
+    }
+
+    #[tokio::test]
+    async fn bundle_external_refs_from_files() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-bundle-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("schemas")).unwrap();
+        // A user refers to its manager, a cycle through two files
+        std::fs::write(dir.join("schemas/user.yaml"), "User:\n  properties:\n    manager:\n      $ref: '#/User'\n    team:\n      $ref: 'team.json'\n").unwrap();
+        std::fs::write(dir.join("schemas/team.json"), r#"{"properties": {"lead": {"$ref": "user.yaml#/User"}, "error": {"$ref": "../openapi.json#/components/schemas/Error"}}}"#).unwrap();
+        std::fs::write(dir.join("parameters.yaml"), "limit:\n  name: limit\n  in: query\n").unwrap();
+        let url = format!("file://{}/openapi.json", dir.display());
+        let document = json!({
+            "openapi": "3.0.0",
+            "paths": {"/users": {"get": {
+                "parameters": [{"$ref": "parameters.yaml#/limit"}],
+                "responses": {"200": {"content": {"application/json": {"schema": {"$ref": "./schemas/user.yaml#/User", "description": "Kept"}}}}}
+            }}},
+            "components": {"schemas": {"Error": {}, "User": {"description": "Taken"}}}
+        });
+        assert!(has_external_refs(&document));
+
+        let bundled = bundle(&HttpClient::new(), &url, document).await.unwrap();
+
+        assert!(!has_external_refs(&bundled));
+        let get = &bundled["paths"]["/users"]["get"];
+        assert_eq!("#/components/parameters/limit", get["parameters"][0]["$ref"]);
+        let schema = &get["responses"]["200"]["content"]["application/json"]["schema"];
+        assert_eq!(("#/components/schemas/User_2", "Kept"), (schema["$ref"].as_str().unwrap(), schema["description"].as_str().unwrap()));
+
+        let schemas = &bundled["components"]["schemas"];
+        assert_eq!("#/components/schemas/User_2", schemas["User_2"]["properties"]["manager"]["$ref"]);
+        assert_eq!("#/components/schemas/team", schemas["User_2"]["properties"]["team"]["$ref"]);
+        assert_eq!("#/components/schemas/User_2", schemas["team"]["properties"]["lead"]["$ref"]);
+        assert_eq!("#/components/schemas/Error", schemas["team"]["properties"]["error"]["$ref"]);
+        assert_eq!("query", bundled["components"]["parameters"]["limit"]["in"]);
+
+        let missing = json!({"openapi": "3.0.0", "paths": {}, "components": {"schemas": {"Missing": {"$ref": "missing.yaml"}}}});
+        assert!(bundle(&HttpClient::new(), &url, missing).await.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn never_read_files_for_fetched_openapi_files() {
+        let document = json!({"openapi": "3.0.0", "paths": {}, "components": {"schemas": {"Secret": {"$ref": "file:///etc/passwd"}}}});
+
+        let result = bundle(&HttpClient::new(), "http://users/openapi.json", document).await;
+
+        assert!(matches!(result, Err(BundleError::Scheme(file)) if file == "file:///etc/passwd"));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::OpenApiConfig;
+use crate::gateway::openapi::{content_hash, parse_openapi, ContentType, ParseError};
+use crate::gateway::{EntryStatus, GatewayEntry};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// OpenAPI file as fetched, before `servers` is removed
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub content_type: ContentType,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedFile {
+    url: String,
+    content_type: ContentType,
+    fetched_at: DateTime<Utc>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    contents: String,
+}
+
+/// Keeps the last fetched OpenAPI file of every entry on disk, one file per entry
+pub struct SpecCache {
+    dir: PathBuf,
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Parse error: {0}")]
+    Parse(#[from] ParseError),
+    #[error("OpenAPI file is not UTF-8")]
+    NotUtf8,
+}
+
+impl SpecCache {
+    pub fn new(dir: PathBuf) -> SpecCache {
+        SpecCache { dir }
+    }
+
+    pub async fn store(&self, entry: &GatewayEntry, source: &Source, fetched_at: DateTime<Utc>) -> Result<(), CacheError> {
+        let file = entry.openapi_file.as_ref();
+        let cached = CachedFile {
+            url: entry.config.url.clone(),
+            content_type: source.content_type,
+            fetched_at,
+            etag: file.and_then(|file| file.etag.clone()),
+            last_modified: file.and_then(|file| file.last_modified.clone()),
+            contents: String::from_utf8(source.contents.clone()).map_err(|_| CacheError::NotUtf8)?,
+        };
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Write next to the cached file first, so a crash never leaves a partial file behind
+        let path = self.path(&entry.config.name);
+        let temp_path = path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec(&cached)?).await?;
+        tokio::fs::rename(temp_path, path).await?;
+
+        Ok(())
+    }
+
+    /// Entry parsed from the cached file, `None` when nothing is cached for the URL of the entry
+    pub async fn load(&self, config: &OpenApiConfig) -> Result<Option<GatewayEntry>, CacheError> {
+        let contents = match tokio::fs::read(self.path(&config.name)).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let cached: CachedFile = serde_json::from_slice(&contents)?;
+
+        if cached.url != config.url {
+            return Ok(None);
+        }
+
+        let mut entry = parse_openapi(cached.content_type, config.clone(), cached.contents.as_bytes())?;
+        if let Some(file) = entry.openapi_file.as_mut() {
+            file.etag = cached.etag;
+            file.last_modified = cached.last_modified;
+        }
+        entry.status = EntryStatus {
+            last_fetch: Some(cached.fetched_at),
+            last_success: Some(cached.fetched_at),
+            ..Default::default()
+        };
+
+        Ok(Some(entry))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        // Entry names may contain any character
+        self.dir.join(format!("{}.json", content_hash(name.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OpenApiConfig;
+    use crate::gateway::cache::{Source, SpecCache};
+    use crate::gateway::openapi::{parse_openapi, ContentType};
+    use chrono::{TimeZone, Utc};
+
+    const SPEC: &str = r#"{"openapi": "3.0.0", "servers": [{"url": "/api"}], "paths": {"/users": {"get": {}}}}"#;
+
+    #[tokio::test]
+    async fn store_and_load() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-cache-{}", std::process::id()));
+        let cache = SpecCache::new(dir.clone());
+        let config = OpenApiConfig { name: "users/v1".to_string(), url: "http://users/openapi.json".to_string(), ..Default::default() };
+        let source = Source { content_type: ContentType::JSON, contents: SPEC.as_bytes().to_vec() };
+        let fetched_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+        let mut entry = parse_openapi(ContentType::JSON, config.clone(), SPEC.as_bytes()).unwrap();
+        entry.openapi_file.as_mut().unwrap().etag = Some("\"v1\"".to_string());
+        cache.store(&entry, &source, fetched_at).await.unwrap();
+
+        let cached = cache.load(&config).await.unwrap().unwrap();
+        assert_eq!("/api/users", cached.routes[0].template);
+        assert_eq!(Some("\"v1\"".to_string()), cached.openapi_file.unwrap().etag);
+        assert_eq!(Some(fetched_at), cached.status.last_success);
+
+        let moved = OpenApiConfig { url: "http://users-v2/openapi.json".to_string(), ..config };
+        assert!(cache.load(&moved).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
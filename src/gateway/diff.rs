@@ -0,0 +1,488 @@
+use crate::openapi::{OpenApiV3, Parameter, PathMethod, RequestBody};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::{Display, Formatter};
+
+/// Number of diffs kept per entry
+pub const HISTORY_LIMIT: usize = 20;
+/// Properties compared per schema, reusable schemas referring to each other can otherwise add up quickly
+const MAX_PROPERTIES: usize = 1000;
+
+/// Difference between two versions of an OpenAPI file, operations are written as `GET /users` and properties of
+/// nested objects as `owner.name`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    OperationAdded { operation: String },
+    OperationRemoved { operation: String },
+    ParameterAdded { operation: String, parameter: String, required: bool },
+    ParameterRemoved { operation: String, parameter: String },
+    ParameterRequiredChanged { operation: String, parameter: String, required: bool },
+    RequestBodyRequiredChanged { operation: String, required: bool },
+    ResponseAdded { operation: String, status: String },
+    ResponseRemoved { operation: String, status: String },
+    RequestPropertyAdded { operation: String, property: String, required: bool },
+    RequestPropertyRemoved { operation: String, property: String },
+    RequestPropertyRequiredChanged { operation: String, property: String, required: bool },
+    ResponsePropertyAdded { operation: String, status: String, property: String },
+    ResponsePropertyRemoved { operation: String, status: String, property: String },
+    ResponsePropertyRequiredChanged { operation: String, status: String, property: String, required: bool },
+}
+
+/// Changes between two fetched versions of the OpenAPI file of an entry
+#[derive(Serialize, Debug, Clone)]
+pub struct SpecChanges {
+    pub detected_at: DateTime<Utc>,
+    pub from_hash: String,
+    pub to_hash: String,
+    pub breaking: bool,
+    pub changes: Vec<Change>,
+}
+
+/// Changes applied to an entry, and breaking changes held back until acknowledged
+#[derive(Debug, Default)]
+pub struct ChangeLog {
+    /// Oldest first, at most `HISTORY_LIMIT` diffs
+    pub history: VecDeque<SpecChanges>,
+    pub pending: Option<PendingUpdate>,
+}
+
+#[derive(Debug)]
+pub struct PendingUpdate {
+    pub changes: SpecChanges,
+    pub entry: Box<crate::gateway::GatewayEntry>,
+}
+
+impl Change {
+    /// Breaking for clients written against the previous version
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            Change::OperationRemoved { .. } | Change::ResponseRemoved { .. } | Change::ResponsePropertyRemoved { .. } => true,
+            Change::ParameterAdded { required, .. }
+            | Change::ParameterRequiredChanged { required, .. }
+            | Change::RequestBodyRequiredChanged { required, .. }
+            | Change::RequestPropertyAdded { required, .. }
+            | Change::RequestPropertyRequiredChanged { required, .. } => *required,
+            // Clients may rely on a property that was always in a response
+            Change::ResponsePropertyRequiredChanged { required, .. } => !*required,
+            Change::OperationAdded { .. }
+            | Change::ParameterRemoved { .. }
+            | Change::ResponseAdded { .. }
+            | Change::RequestPropertyRemoved { .. }
+            | Change::ResponsePropertyAdded { .. } => false,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::OperationAdded { operation } => write!(f, "added {operation}"),
+            Change::OperationRemoved { operation } => write!(f, "removed {operation}"),
+            Change::ParameterAdded { operation, parameter, required: true } => write!(f, "{operation} added required parameter {parameter}"),
+            Change::ParameterAdded { operation, parameter, required: false } => write!(f, "{operation} added parameter {parameter}"),
+            Change::ParameterRemoved { operation, parameter } => write!(f, "{operation} removed parameter {parameter}"),
+            Change::ParameterRequiredChanged { operation, parameter, required: true } => write!(f, "{operation} requires parameter {parameter}"),
+            Change::ParameterRequiredChanged { operation, parameter, required: false } => write!(f, "{operation} no longer requires parameter {parameter}"),
+            Change::RequestBodyRequiredChanged { operation, required: true } => write!(f, "{operation} requires a request body"),
+            Change::RequestBodyRequiredChanged { operation, required: false } => write!(f, "{operation} no longer requires a request body"),
+            Change::ResponseAdded { operation, status } => write!(f, "{operation} added response {status}"),
+            Change::ResponseRemoved { operation, status } => write!(f, "{operation} removed response {status}"),
+            Change::RequestPropertyAdded { operation, property, required: true } => write!(f, "{operation} added required request body property {property}"),
+            Change::RequestPropertyAdded { operation, property, required: false } => write!(f, "{operation} added request body property {property}"),
+            Change::RequestPropertyRemoved { operation, property } => write!(f, "{operation} removed request body property {property}"),
+            Change::RequestPropertyRequiredChanged { operation, property, required: true } => write!(f, "{operation} requires request body property {property}"),
+            Change::RequestPropertyRequiredChanged { operation, property, required: false } => write!(f, "{operation} no longer requires request body property {property}"),
+            Change::ResponsePropertyAdded { operation, status, property } => write!(f, "{operation} added property {property} to response {status}"),
+            Change::ResponsePropertyRemoved { operation, status, property } => write!(f, "{operation} removed property {property} from response {status}"),
+            Change::ResponsePropertyRequiredChanged { operation, status, property, required: true } => write!(f, "{operation} always has property {property} in response {status}"),
+            Change::ResponsePropertyRequiredChanged { operation, status, property, required: false } => write!(f, "{operation} no longer always has property {property} in response {status}"),
+        }
+    }
+}
+
+impl SpecChanges {
+    pub fn new(detected_at: DateTime<Utc>, from_hash: String, to_hash: String, changes: Vec<Change>) -> SpecChanges {
+        SpecChanges {
+            detected_at,
+            from_hash,
+            to_hash,
+            breaking: changes.iter().any(Change::is_breaking),
+            changes,
+        }
+    }
+}
+
+impl Display for SpecChanges {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let changes: Vec<String> = self.changes.iter().map(Change::to_string).collect();
+
+        write!(f, "{}", changes.join(", "))
+    }
+}
+
+impl ChangeLog {
+    pub fn push(&mut self, changes: SpecChanges) {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(changes);
+    }
+}
+
+/// Operations, parameters, responses and properties of request and response bodies changed from `old` to `new`
+pub fn diff(old: &OpenApiV3, new: &OpenApiV3) -> Vec<Change> {
+    let old_operations = operations(old);
+    let new_operations = operations(new);
+    let mut changes = vec![];
+
+    for (operation, old_operation) in &old_operations {
+        match new_operations.get(operation) {
+            Some(new_operation) => changes.extend(diff_operation(operation, old_operation, new_operation)),
+            None => changes.push(Change::OperationRemoved { operation: operation.clone() }),
+        }
+    }
+    for operation in new_operations.keys().filter(|operation| !old_operations.contains_key(*operation)) {
+        changes.push(Change::OperationAdded { operation: operation.clone() });
+    }
+
+    changes
+}
+
+/// Operation with the parameters it accepts
+struct Operation<'a> {
+    document: &'a OpenApiV3,
+    method: &'a PathMethod,
+    parameters: Vec<Parameter>,
+}
+
+/// Property of a schema changed between versions
+enum PropertyChange {
+    Added { required: bool },
+    Removed,
+    RequiredChanged { required: bool },
+}
+
+fn operations(document: &OpenApiV3) -> BTreeMap<String, Operation<'_>> {
+    document.paths
+        .iter()
+        .flat_map(|(path_name, path)| {
+            path.methods.iter().map(move |(method_name, method)| {
+                let operation = Operation {
+                    document,
+                    method,
+                    parameters: document.parameters(path, method),
+                };
+
+                (format!("{} {}", method_name.to_uppercase(), path_name), operation)
+            })
+        })
+        .collect()
+}
+
+fn diff_operation<'a>(operation: &str, old: &Operation<'a>, new: &Operation<'a>) -> Vec<Change> {
+    let mut changes = vec![];
+    let parameter_name = |parameter: &Parameter| format!("{} in {}", parameter.name, parameter.in_type);
+    let find = |parameters: &[Parameter], parameter: &Parameter| {
+        parameters.iter()
+            .find(|other| other.name == parameter.name && other.in_type == parameter.in_type)
+            .map(|other| other.required)
+    };
+
+    for parameter in &old.parameters {
+        match find(&new.parameters, parameter) {
+            Some(required) if required != parameter.required => changes.push(Change::ParameterRequiredChanged {
+                operation: operation.to_string(),
+                parameter: parameter_name(parameter),
+                required,
+            }),
+            Some(_) => {}
+            None => changes.push(Change::ParameterRemoved { operation: operation.to_string(), parameter: parameter_name(parameter) }),
+        }
+    }
+    for parameter in new.parameters.iter().filter(|parameter| find(&old.parameters, parameter).is_none()) {
+        changes.push(Change::ParameterAdded {
+            operation: operation.to_string(),
+            parameter: parameter_name(parameter),
+            required: parameter.required,
+        });
+    }
+
+    let request_body = |operation: &Operation<'a>| operation.document.request_body(operation.method);
+    let body_required = |operation: &Operation<'a>| request_body(operation).map(|body| body.required).unwrap_or(false);
+    if body_required(old) != body_required(new) {
+        changes.push(Change::RequestBodyRequiredChanged { operation: operation.to_string(), required: body_required(new) });
+    }
+
+    let body_schemas = |operation: &Operation<'a>| request_body(operation).map(RequestBody::schemas).unwrap_or_default();
+    for (property, change) in changed_properties(old.document, &body_schemas(old), new.document, &body_schemas(new)) {
+        let operation = operation.to_string();
+        changes.push(match change {
+            PropertyChange::Added { required } => Change::RequestPropertyAdded { operation, property, required },
+            PropertyChange::Removed => Change::RequestPropertyRemoved { operation, property },
+            PropertyChange::RequiredChanged { required } => Change::RequestPropertyRequiredChanged { operation, property, required },
+        });
+    }
+
+    for status in old.method.responses.keys().filter(|status| !new.method.responses.contains_key(*status)) {
+        changes.push(Change::ResponseRemoved { operation: operation.to_string(), status: status.clone() });
+    }
+    for status in new.method.responses.keys().filter(|status| !old.method.responses.contains_key(*status)) {
+        changes.push(Change::ResponseAdded { operation: operation.to_string(), status: status.clone() });
+    }
+
+    for (status, old_response) in &old.method.responses {
+        let Some(new_response) = new.method.responses.get(status) else {
+            continue;
+        };
+        let response_schemas = |operation: &Operation<'a>, response| operation.document.response(response)
+            .map(|response| response.schemas())
+            .unwrap_or_default();
+        let old_schemas = response_schemas(old, old_response);
+        let new_schemas = response_schemas(new, new_response);
+        for (property, change) in changed_properties(old.document, &old_schemas, new.document, &new_schemas) {
+            let (operation, status) = (operation.to_string(), status.clone());
+            changes.push(match change {
+                PropertyChange::Added { .. } => Change::ResponsePropertyAdded { operation, status, property },
+                PropertyChange::Removed => Change::ResponsePropertyRemoved { operation, status, property },
+                PropertyChange::RequiredChanged { required } => Change::ResponsePropertyRequiredChanged { operation, status, property, required },
+            });
+        }
+    }
+
+    changes
+}
+
+/// Properties changed between the schemas of the media types both versions have. Of added or removed
+/// objects, only the object itself is reported and not its properties.
+fn changed_properties(
+    old_document: &OpenApiV3,
+    old_schemas: &BTreeMap<&str, &Value>,
+    new_document: &OpenApiV3,
+    new_schemas: &BTreeMap<&str, &Value>,
+) -> BTreeMap<String, PropertyChange> {
+    let mut changed = BTreeMap::new();
+
+    for (media_type, old_schema) in old_schemas {
+        let Some(new_schema) = new_schemas.get(media_type) else {
+            continue;
+        };
+        let old_properties = properties(old_document, old_schema);
+        let new_properties = properties(new_document, new_schema);
+        let parent_changed = |property: &str| property.rsplit_once('.')
+            .map(|(parent, _)| old_properties.contains_key(parent) != new_properties.contains_key(parent))
+            .unwrap_or(false);
+
+        for property in old_properties.keys().chain(new_properties.keys()) {
+            let change = match (old_properties.get(property), new_properties.get(property)) {
+                (Some(old), Some(new)) if old != new => PropertyChange::RequiredChanged { required: *new },
+                (Some(_), None) if !parent_changed(property) => PropertyChange::Removed,
+                (None, Some(new)) if !parent_changed(property) => PropertyChange::Added { required: *new },
+                _ => continue,
+            };
+            changed.insert(property.clone(), change);
+        }
+    }
+
+    changed
+}
+
+/// Properties of a schema and whether they are required, following `$ref`s and merging `allOf`. Properties of
+/// array items count as those of the array.
+fn properties(document: &OpenApiV3, schema: &Value) -> BTreeMap<String, bool> {
+    let mut declared = BTreeSet::new();
+    let mut required = BTreeSet::new();
+    collect_properties(document, schema, "", &mut vec![], &mut declared, &mut required);
+
+    declared.into_iter()
+        .map(|property| {
+            let required = required.contains(&property);
+            (property, required)
+        })
+        .collect()
+}
+
+fn collect_properties<'a>(
+    document: &'a OpenApiV3,
+    schema: &'a Value,
+    prefix: &str,
+    references: &mut Vec<&'a str>,
+    declared: &mut BTreeSet<String>,
+    required: &mut BTreeSet<String>,
+) {
+    if declared.len() >= MAX_PROPERTIES {
+        return;
+    }
+    let path = |name: &str| match prefix {
+        "" => name.to_string(),
+        prefix => format!("{prefix}.{name}"),
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        // Recursive schemas are followed once
+        if let (false, Some(resolved)) = (references.contains(&reference), document.schema(reference)) {
+            references.push(reference);
+            collect_properties(document, resolved, prefix, references, declared, required);
+            references.pop();
+        }
+        return;
+    }
+
+    for schema in schema.get("allOf").and_then(Value::as_array).into_iter().flatten() {
+        collect_properties(document, schema, prefix, references, declared, required);
+    }
+    if let Some(items) = schema.get("items") {
+        collect_properties(document, items, prefix, references, declared, required);
+    }
+    for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+        required.insert(path(name));
+    }
+    for (name, property) in schema.get("properties").and_then(Value::as_object).into_iter().flatten() {
+        declared.insert(path(name));
+        collect_properties(document, property, &path(name), references, declared, required);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OpenApiConfig;
+    use crate::gateway::diff::{diff, Change};
+    use crate::gateway::openapi::{parse_openapi, ContentType};
+    use crate::openapi::OpenApiV3;
+    use chrono::Utc;
+    use serde_json::json;
+
+    const V1: &str = r#"{"openapi": "3.0.0", "paths": {
+        "/users": {
+            "get": {"parameters": [{"name": "limit", "in": "query"}], "responses": {"200": {}, "404": {}}},
+            "post": {"requestBody": {"required": false}}
+        },
+        "/orders": {"get": {}}
+    }}"#;
+    const V2: &str = r#"{"openapi": "3.0.0", "paths": {
+        "/users": {
+            "get": {"parameters": [{"name": "limit", "in": "query", "required": true}, {"name": "page", "in": "query"}], "responses": {"200": {}, "x-internal": {}}},
+            "post": {"requestBody": {"required": true}}
+        },
+        "/invoices": {"get": {}}
+    }}"#;
+
+    #[test]
+    fn detects_breaking_changes() {
+        let v1: OpenApiV3 = serde_json::from_str(V1).unwrap();
+        let v2: OpenApiV3 = serde_json::from_str(V2).unwrap();
+
+        let changes = diff(&v1, &v2);
+
+        assert_eq!(
+            vec![
+                Change::OperationRemoved { operation: "GET /orders".to_string() },
+                Change::ParameterRequiredChanged { operation: "GET /users".to_string(), parameter: "limit in query".to_string(), required: true },
+                Change::ParameterAdded { operation: "GET /users".to_string(), parameter: "page in query".to_string(), required: false },
+                Change::ResponseRemoved { operation: "GET /users".to_string(), status: "404".to_string() },
+                Change::RequestBodyRequiredChanged { operation: "POST /users".to_string(), required: true },
+                Change::OperationAdded { operation: "GET /invoices".to_string() },
+            ],
+            changes
+        );
+        assert_eq!(
+            vec![true, true, false, true, true, false],
+            changes.iter().map(Change::is_breaking).collect::<Vec<_>>()
+        );
+        assert!(diff(&v1, &v1).is_empty());
+    }
+
+    #[test]
+    fn detects_schema_changes() {
+        let v1: OpenApiV3 = serde_json::from_value(json!({"openapi": "3.0.0", "paths": {"/users": {
+            "post": {
+                "requestBody": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/NewUser"}}}},
+                "responses": {"201": {"$ref": "#/components/responses/User"}}
+            }
+        }}, "components": {
+            "schemas": {
+                "NewUser": {"properties": {"name": {}, "email": {}}, "required": ["name"]},
+                // Followed once, so `manager` has no properties
+                "User": {"allOf": [{"$ref": "#/components/schemas/NewUser"}, {"properties": {"id": {}, "manager": {"$ref": "#/components/schemas/User"}}, "required": ["id"]}]}
+            },
+            "responses": {"User": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}}}
+        }})).unwrap();
+        let v2: OpenApiV3 = serde_json::from_value(json!({"openapi": "3.0.0", "paths": {"/users": {
+            "post": {
+                "requestBody": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/NewUser"}}}},
+                "responses": {"201": {"$ref": "#/components/responses/User"}}
+            }
+        }}, "components": {
+            "schemas": {
+                "NewUser": {"properties": {"name": {}, "email": {}, "address": {"properties": {"city": {}}}}, "required": ["name", "email"]},
+                "User": {"allOf": [{"$ref": "#/components/schemas/NewUser"}, {"properties": {"manager": {"$ref": "#/components/schemas/User"}}}]}
+            },
+            "responses": {"User": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}}}
+        }})).unwrap();
+
+        let changes = diff(&v1, &v2);
+
+        let property_change = |property: &str, required| Change::RequestPropertyRequiredChanged {
+            operation: "POST /users".to_string(),
+            property: property.to_string(),
+            required,
+        };
+        assert_eq!(
+            vec![
+                Change::RequestPropertyAdded { operation: "POST /users".to_string(), property: "address".to_string(), required: false },
+                property_change("email", true),
+                Change::ResponsePropertyAdded { operation: "POST /users".to_string(), status: "201".to_string(), property: "address".to_string() },
+                Change::ResponsePropertyRequiredChanged {
+                    operation: "POST /users".to_string(),
+                    status: "201".to_string(),
+                    property: "email".to_string(),
+                    required: true,
+                },
+                Change::ResponsePropertyRemoved { operation: "POST /users".to_string(), status: "201".to_string(), property: "id".to_string() },
+            ],
+            changes
+        );
+        assert_eq!(
+            vec![false, true, false, false, true],
+            changes.iter().map(Change::is_breaking).collect::<Vec<_>>()
+        );
+        assert!(!property_change("email", false).is_breaking());
+
+        // The other way around, response properties are no longer always there
+        let changes = diff(&v2, &v1);
+        assert!(changes.contains(&Change::ResponsePropertyRequiredChanged {
+            operation: "POST /users".to_string(),
+            status: "201".to_string(),
+            property: "email".to_string(),
+            required: false,
+        }));
+        assert!(changes.iter().filter(|change| matches!(change, Change::ResponsePropertyRequiredChanged { .. })).all(Change::is_breaking));
+        assert!(diff(&v1, &v1).is_empty());
+    }
+
+    #[test]
+    fn hold_back_breaking_changes_until_acknowledged() {
+        let config = OpenApiConfig { name: "users".to_string(), block_breaking_changes: true, ..Default::default() };
+        let mut entry = parse_openapi(ContentType::JSON, config.clone(), V1.as_bytes()).unwrap();
+
+        entry.update(parse_openapi(ContentType::JSON, config.clone(), V2.as_bytes()).unwrap(), Utc::now());
+        assert_eq!(3, entry.routes.len());
+        assert!(entry.changes.history.is_empty());
+        assert!(entry.changes.pending.as_ref().unwrap().changes.breaking);
+
+        assert!(entry.acknowledge());
+        assert!(entry.routes.iter().any(|route| route.template == "/invoices"));
+        assert!(entry.changes.pending.is_none());
+        assert_eq!(6, entry.changes.history[0].changes.len());
+        assert!(!entry.acknowledge());
+
+        // Without blocking, breaking changes are applied and recorded right away
+        let config = OpenApiConfig { block_breaking_changes: false, ..config };
+        let mut entry = parse_openapi(ContentType::JSON, config.clone(), V1.as_bytes()).unwrap();
+        entry.update(parse_openapi(ContentType::JSON, config, V2.as_bytes()).unwrap(), Utc::now());
+        assert!(entry.changes.pending.is_none());
+        assert!(entry.changes.history[0].breaking);
+    }
+}
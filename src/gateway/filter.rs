@@ -0,0 +1,250 @@
+use crate::config::OpenApiConfig;
+use crate::openapi::METHODS;
+use regex::{escape, Regex};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Sections of `components` that are referenced with `$ref`, security schemes are referenced by name
+pub const COMPONENT_SECTIONS: [&str; 9] = ["schemas", "responses", "parameters", "examples", "requestBodies", "headers", "links", "callbacks", "pathItems"];
+
+/// Removes the operations the entry does not expose and those marked with one of its `hidden_extensions`,
+/// so they are neither routed nor published. Webhooks are filtered the same way, except for `exclude_paths`.
+/// Components only used by removed operations are removed as well.
+pub fn filter_operations(document: &mut Value, config: &OpenApiConfig) {
+    let exclude_paths: Vec<Regex> = config.exclude_paths.iter().map(|glob| glob_regex(glob)).collect();
+
+    let mut removed = false;
+    for (section, exclude) in [("paths", exclude_paths.as_slice()), ("webhooks", &[])] {
+        let Some(items) = document.get_mut(section).and_then(Value::as_object_mut) else {
+            continue;
+        };
+
+        items.retain(|path, item| {
+            if exclude.iter().any(|regex| regex.is_match(path)) || is_hidden(item, config) {
+                removed = true;
+                return false;
+            }
+
+            let Some(item) = item.as_object_mut() else {
+                return true;
+            };
+            item.retain(|key, operation| {
+                let exposed = !METHODS.contains(&key.as_str()) || (!is_hidden(operation, config) && is_exposed(operation, config));
+                removed |= !exposed;
+                exposed
+            });
+
+            item.keys().any(|key| METHODS.contains(&key.as_str()) || key == "$ref")
+        });
+    }
+
+    if removed {
+        prune_components(document);
+    }
+}
+
+/// Exclusions win over inclusions, an operation matching any inclusion is included
+fn is_exposed(operation: &Value, config: &OpenApiConfig) -> bool {
+    let tags: Vec<&str> = operation.get("tags")
+        .and_then(Value::as_array)
+        .map(|tags| tags.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let operation_id = operation.get("operationId").and_then(Value::as_str);
+
+    if tags.iter().any(|tag| config.exclude_tags.iter().any(|excluded| excluded == tag)) {
+        return false;
+    }
+    if config.include_tags.is_empty() && config.include_operations.is_empty() {
+        return true;
+    }
+
+    tags.iter().any(|tag| config.include_tags.iter().any(|included| included == tag))
+        || operation_id.map(|id| config.include_operations.iter().any(|included| included == id)).unwrap_or(false)
+}
+
+/// Marked with `x-internal: true` or another of the hidden extensions of the entry
+fn is_hidden(value: &Value, config: &OpenApiConfig) -> bool {
+    config.hidden_extensions.iter().any(|extension| value.get(extension) == Some(&Value::Bool(true)))
+}
+
+/// `*` matches within a path segment, `**` across segments
+pub fn glob_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    Regex::new(&pattern).unwrap()
+}
+
+/// Removes components that are not referenced, directly or through other components, from outside the components
+pub fn prune_components(document: &mut Value) {
+    // Swagger 2 keeps its components at the top level
+    let containers: Vec<String> = if document.get("swagger").is_some() {
+        ["definitions", "parameters", "responses"].iter().map(|container| format!("/{container}")).collect()
+    } else {
+        COMPONENT_SECTIONS.iter().map(|section| format!("/components/{section}")).collect()
+    };
+
+    let mut outside = document.clone();
+    for container in &containers {
+        if let Some((parent, key)) = container.rsplit_once('/') {
+            if let Some(parent) = pointer_mut(&mut outside, parent) {
+                parent.remove(key);
+            }
+        }
+    }
+
+    let mut pending = vec![];
+    collect_refs(&outside, &mut pending);
+    let mut used: HashSet<String> = HashSet::new();
+    while let Some(reference) = pending.pop() {
+        let Some(component) = containers.iter().find_map(|container| component_pointer(&reference, container)) else {
+            continue;
+        };
+        if used.insert(component.clone()) {
+            if let Some(value) = document.pointer(&component) {
+                collect_refs(value, &mut pending);
+            }
+        }
+    }
+
+    for container in &containers {
+        if let Some(components) = pointer_mut(document, container) {
+            components.retain(|name, _| used.contains(&format!("{container}/{name}")));
+        }
+    }
+}
+
+fn pointer_mut<'a>(document: &'a mut Value, pointer: &str) -> Option<&'a mut serde_json::Map<String, Value>> {
+    match pointer {
+        "" => document.as_object_mut(),
+        pointer => document.pointer_mut(pointer).and_then(Value::as_object_mut),
+    }
+}
+
+/// Pointer to the component a local `$ref` points into, e.g. `/components/schemas/Pet` for
+/// `#/components/schemas/Pet/properties/id`
+fn component_pointer(reference: &str, container: &str) -> Option<String> {
+    let rest = reference.strip_prefix('#')?.strip_prefix(container)?.strip_prefix('/')?;
+    let name = rest.split('/').next()?;
+
+    Some(format!("{container}/{name}"))
+}
+
+fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => refs.push(reference.clone()),
+                    (_, value) => collect_refs(value, refs),
+                }
+            }
+        }
+        Value::Array(values) => values.iter().for_each(|value| collect_refs(value, refs)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OpenApiConfig;
+    use crate::gateway::filter::{filter_operations, glob_regex};
+    use serde_json::json;
+
+    fn document() -> serde_json::Value {
+        json!({
+            "openapi": "3.0.0",
+            "paths": {
+                "/pets": {
+                    "get": {"tags": ["pets"], "operationId": "listPets", "responses": {"200": {"$ref": "#/components/responses/Pets"}}},
+                    "post": {"tags": ["pets", "admin"], "operationId": "createPet"}
+                },
+                "/users/{id}": {
+                    "parameters": [{"name": "id", "in": "path"}],
+                    "get": {"tags": ["users"], "operationId": "getUser", "responses": {"200": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}}}}
+                },
+                "/internal/health/live": {"get": {"operationId": "live"}}
+            },
+            "components": {
+                "responses": {"Pets": {"content": {"application/json": {"schema": {"type": "array", "items": {"$ref": "#/components/schemas/Pet"}}}}}},
+                "schemas": {"Pet": {"type": "object"}, "User": {"properties": {"address": {"$ref": "#/components/schemas/Address"}}}, "Address": {}},
+                "securitySchemes": {"token": {"type": "http"}}
+            }
+        })
+    }
+
+    #[test]
+    fn include_tags_and_operations() {
+        let config = OpenApiConfig {
+            include_tags: vec!["pets".to_string()],
+            exclude_tags: vec!["admin".to_string()],
+            include_operations: vec!["live".to_string()],
+            ..Default::default()
+        };
+        let mut document = document();
+
+        filter_operations(&mut document, &config);
+
+        assert_eq!(json!(["/internal/health/live", "/pets"]), json!(document["paths"].as_object().unwrap().keys().collect::<Vec<_>>()));
+        assert!(document["paths"]["/pets"].get("post").is_none());
+        assert_eq!(json!({"Pet": {"type": "object"}}), document["components"]["schemas"]);
+        assert!(document["components"]["responses"].get("Pets").is_some());
+        assert!(document["components"]["securitySchemes"].get("token").is_some());
+    }
+
+    #[test]
+    fn exclude_paths() {
+        let config = OpenApiConfig { exclude_paths: vec!["/internal/**".to_string(), "/pets".to_string()], ..Default::default() };
+        let mut document = document();
+
+        filter_operations(&mut document, &config);
+
+        assert_eq!(json!(["/users/{id}"]), json!(document["paths"].as_object().unwrap().keys().collect::<Vec<_>>()));
+        assert_eq!(json!(["Address", "User"]), json!(document["components"]["schemas"].as_object().unwrap().keys().collect::<Vec<_>>()));
+
+        // Nothing filtered, unused components are kept
+        let mut document = json!({"openapi": "3.0.0", "paths": {}, "components": {"schemas": {"Unused": {}}}});
+        filter_operations(&mut document, &OpenApiConfig::default());
+        assert!(document["components"]["schemas"].get("Unused").is_some());
+    }
+
+    #[test]
+    fn hide_operations_and_paths_with_extensions() {
+        let mut document = json!({
+            "openapi": "3.0.0",
+            "paths": {
+                "/users": {"get": {}, "delete": {"x-internal": true}, "put": {"x-internal": false}},
+                "/admin": {"x-gateway-hidden": true, "get": {}},
+                "/debug": {"get": {"x-debug": true}}
+            }
+        });
+
+        filter_operations(&mut document, &OpenApiConfig::default());
+        assert_eq!(json!({"/debug": {"get": {"x-debug": true}}, "/users": {"get": {}, "put": {"x-internal": false}}}), document["paths"]);
+
+        filter_operations(&mut document, &OpenApiConfig { hidden_extensions: vec!["x-debug".to_string()], ..Default::default() });
+        assert!(document["paths"].get("/debug").is_none());
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_regex("/users/*").is_match("/users/{id}"));
+        assert!(!glob_regex("/users/*").is_match("/users/{id}/orders"));
+        assert!(glob_regex("/users/**").is_match("/users/{id}/orders"));
+        assert!(glob_regex("/v?/users").is_match("/v1/users"));
+        assert!(!glob_regex("/users").is_match("/users/{id}"));
+    }
+}
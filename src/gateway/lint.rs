@@ -0,0 +1,278 @@
+use crate::openapi::METHODS;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problem found in an OpenAPI file, `pointer` is the JSON pointer to where it was found
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub pointer: String,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write!(f, "{severity} at {}: {}", self.pointer, self.message)
+    }
+}
+
+impl Diagnostic {
+    fn error(pointer: String, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, pointer, message }
+    }
+
+    fn warning(pointer: String, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, pointer, message }
+    }
+}
+
+/// Unresolvable `$ref`s, duplicate operation ids and path parameters that do not match the path
+pub fn lint(document: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    lint_refs(document, document, &mut String::new(), &mut diagnostics);
+    lint_operations(document, &mut diagnostics);
+
+    // Path level parameters are checked for every operation of the path
+    let mut unique: Vec<Diagnostic> = vec![];
+    for diagnostic in diagnostics {
+        if !unique.contains(&diagnostic) {
+            unique.push(diagnostic);
+        }
+    }
+
+    unique
+}
+
+fn lint_refs(document: &Value, value: &Value, pointer: &mut String, diagnostics: &mut Vec<Diagnostic>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get("$ref") {
+                let ref_pointer = format!("{pointer}/$ref");
+                match reference.strip_prefix('#') {
+                    Some(target) if resolve(document, target).is_none() => {
+                        diagnostics.push(Diagnostic::error(ref_pointer, format!("$ref {reference} can not be resolved")));
+                    }
+                    Some(_) => {}
+                    None => diagnostics.push(Diagnostic::error(ref_pointer, format!("$ref {reference} to another file is not bundled"))),
+                }
+            }
+
+            for (key, value) in object.iter().filter(|(key, _)| *key != "$ref") {
+                lint_child_refs(document, key, value, pointer, diagnostics);
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                lint_child_refs(document, &index.to_string(), value, pointer, diagnostics);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn lint_child_refs(document: &Value, key: &str, value: &Value, pointer: &mut String, diagnostics: &mut Vec<Diagnostic>) {
+    let length = pointer.len();
+    pointer.push('/');
+    pointer.push_str(&escape(key));
+    lint_refs(document, value, pointer, diagnostics);
+    pointer.truncate(length);
+}
+
+fn lint_operations(document: &Value, diagnostics: &mut Vec<Diagnostic>) {
+    // Pointer to the first operation using an operation id
+    let mut operation_ids: HashMap<&str, String> = HashMap::new();
+
+    for section in ["paths", "webhooks"] {
+        for (path, item) in document.get(section).and_then(Value::as_object).into_iter().flatten() {
+            let item_pointer = format!("/{section}/{}", escape(path));
+            let Some(item) = item.as_object() else {
+                diagnostics.push(Diagnostic::error(item_pointer, "path item must be an object".to_string()));
+                continue;
+            };
+
+            for (method, operation) in item.iter().filter(|(key, _)| METHODS.contains(&key.as_str())) {
+                let pointer = format!("{item_pointer}/{method}");
+
+                if let Some(operation_id) = operation.get("operationId").and_then(Value::as_str) {
+                    match operation_ids.get(operation_id) {
+                        Some(first) => diagnostics.push(Diagnostic::error(
+                            format!("{pointer}/operationId"),
+                            format!("operationId {operation_id} is also used by {first}"),
+                        )),
+                        None => {
+                            operation_ids.insert(operation_id, pointer.clone());
+                        }
+                    }
+                }
+
+                // Webhooks are not called at a path of the API
+                if section == "paths" {
+                    lint_path_parameters(document, path, item.get("parameters"), operation, &pointer, diagnostics);
+                }
+            }
+        }
+    }
+}
+
+/// Path parameters declared for an operation must match the `{name}`s of its path
+fn lint_path_parameters(
+    document: &Value,
+    path: &str,
+    path_parameters: Option<&Value>,
+    operation: &Value,
+    pointer: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let item_pointer = pointer.rsplit_once('/').map(|(item_pointer, _)| item_pointer).unwrap_or("");
+    let declared = [(path_parameters, item_pointer.to_string()), (operation.get("parameters"), pointer.to_string())];
+
+    // Operation parameters override those of the path with the same name
+    let mut parameters: Vec<(&str, String, &Value)> = vec![];
+    for (list, list_pointer) in declared {
+        for (index, parameter) in list.and_then(Value::as_array).into_iter().flatten().enumerate() {
+            let parameter_pointer = format!("{list_pointer}/parameters/{index}");
+            let Some(parameter) = resolve_parameter(document, parameter) else {
+                continue;
+            };
+            let (Some(name), Some(location)) = (parameter.get("name").and_then(Value::as_str), parameter.get("in").and_then(Value::as_str)) else {
+                diagnostics.push(Diagnostic::error(parameter_pointer, "parameter must have a name and in".to_string()));
+                continue;
+            };
+            if location != "path" {
+                continue;
+            }
+
+            parameters.retain(|(other, _, _)| *other != name);
+            parameters.push((name, parameter_pointer, parameter));
+        }
+    }
+
+    let templated = templated_names(path);
+    for (name, parameter_pointer, parameter) in &parameters {
+        if !templated.contains(name) {
+            diagnostics.push(Diagnostic::error(parameter_pointer.clone(), format!("path parameter {name} is not in path {path}")));
+        } else if parameter.get("required") != Some(&Value::Bool(true)) {
+            diagnostics.push(Diagnostic::warning(parameter_pointer.clone(), format!("path parameter {name} must be required")));
+        }
+    }
+    for name in templated.iter().filter(|name| !parameters.iter().any(|(declared, _, _)| declared == *name)) {
+        diagnostics.push(Diagnostic::error(pointer.to_string(), format!("path parameter {name} is not declared")));
+    }
+}
+
+/// Follows local `$ref`s of a parameter, unresolvable ones are reported by `lint_refs`
+fn resolve_parameter<'a>(document: &'a Value, parameter: &'a Value) -> Option<&'a Value> {
+    let mut parameter = parameter;
+    for _ in 0..8 {
+        match parameter.get("$ref").and_then(Value::as_str) {
+            Some(reference) => parameter = resolve(document, reference.strip_prefix('#')?)?,
+            None => return Some(parameter),
+        }
+    }
+
+    None
+}
+
+fn resolve<'a>(document: &'a Value, pointer: &str) -> Option<&'a Value> {
+    match pointer {
+        "" => Some(document),
+        pointer => document.pointer(pointer),
+    }
+}
+
+/// Names between `{` and `}` in a path template
+fn templated_names(path: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = path;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        names.push(&rest[start + 1..end]);
+        rest = &rest[end + 1..];
+    }
+
+    names
+}
+
+/// Key as segment of a JSON pointer
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gateway::lint::{lint, Diagnostic, Severity};
+    use serde_json::json;
+
+    #[test]
+    fn diagnostics_with_pointers() {
+        let document = json!({
+            "openapi": "3.0.0",
+            "paths": {
+                "/users/{id}": {
+                    "parameters": [{"$ref": "#/components/parameters/id"}],
+                    "get": {"operationId": "getUser"},
+                    "put": {"operationId": "getUser", "parameters": [{"name": "id", "in": "path"}]}
+                },
+                "/orders/{orderId}": {
+                    "get": {"parameters": [{"name": "id", "in": "path", "required": true}, {"in": "query"}], "responses": {"200": {"$ref": "#/components/responses/Missing"}}}
+                }
+            },
+            "components": {"parameters": {"id": {"name": "id", "in": "path", "required": true}}}
+        });
+
+        let diagnostics = lint(&document);
+
+        assert_eq!(
+            vec![
+                Diagnostic {
+                    severity: Severity::Error,
+                    pointer: "/paths/~1orders~1{orderId}/get/responses/200/$ref".to_string(),
+                    message: "$ref #/components/responses/Missing can not be resolved".to_string(),
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    pointer: "/paths/~1orders~1{orderId}/get/parameters/1".to_string(),
+                    message: "parameter must have a name and in".to_string(),
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    pointer: "/paths/~1orders~1{orderId}/get/parameters/0".to_string(),
+                    message: "path parameter id is not in path /orders/{orderId}".to_string(),
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    pointer: "/paths/~1orders~1{orderId}/get".to_string(),
+                    message: "path parameter orderId is not declared".to_string(),
+                },
+                Diagnostic {
+                    severity: Severity::Error,
+                    pointer: "/paths/~1users~1{id}/put/operationId".to_string(),
+                    message: "operationId getUser is also used by /paths/~1users~1{id}/get".to_string(),
+                },
+                Diagnostic {
+                    severity: Severity::Warning,
+                    pointer: "/paths/~1users~1{id}/put/parameters/0".to_string(),
+                    message: "path parameter id must be required".to_string(),
+                },
+            ],
+            diagnostics
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use crate::gateway::{GatewayEntry, OpenApiFile};
+use crate::openapi::METHODS;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Combines the OpenAPI files of all entries into one document with the gateway at `public_url` as the only
+/// server, paths include the base path of their entry. Component names used by several entries are prefixed with the entry name, the first entry wins
+/// when entries define the same operation, as it does when proxying. Webhooks are included when the first entry
+/// is OpenAPI 3.1, the version the merged document is written in.
+pub fn merge_openapi(entries: &[GatewayEntry], public_url: &str) -> Value {
+    let documents: Vec<(&str, &OpenApiFile, Value)> = entries.iter()
+        .filter_map(|entry| {
+            let file = entry.openapi_file.as_ref()?;
+            let document: Value = serde_json::from_slice(&file.contents).ok()?;
+
+            // Swagger 2.0 files can not be combined with OpenAPI 3 files
+            let version = document.get("openapi").and_then(Value::as_str)?;
+            version.starts_with("3.").then_some((entry.config.name.as_str(), file, document))
+        })
+        .collect();
+
+    let collisions = component_collisions(&documents);
+    let mut paths = Map::new();
+    let mut webhooks = Map::new();
+    let mut components: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    let mut tags: Vec<Value> = vec![];
+
+    for (name, file, mut document) in documents.iter().cloned() {
+        let renames = renames(name, &document, &collisions);
+        rename_refs(&mut document, &renames);
+        let security = document.get("security").cloned();
+
+        for (section, values) in document.get("components").and_then(Value::as_object).into_iter().flatten() {
+            let merged = components.entry(section.clone()).or_default();
+            for (component, value) in values.as_object().into_iter().flatten() {
+                let component = renames.get(&(section.clone(), component.clone())).unwrap_or(component);
+                merged.insert(component.clone(), value.clone());
+            }
+        }
+
+        for tag in document.get("tags").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(tag_name) = tag.get("name").and_then(Value::as_str) {
+                let mut tag = tag.clone();
+                add_tag(&mut tags, tag_name, name, tag.as_object_mut());
+            }
+        }
+
+        let items = document.get("paths").and_then(Value::as_object).into_iter().flatten()
+            .flat_map(|(path, item)| file.base_paths_of(path).iter().map(move |base_path| (format!("{base_path}{path}"), item)));
+        for (path, item) in items {
+            let Some(item) = item.as_object() else {
+                continue;
+            };
+            let merged = paths.entry(path.clone()).or_insert_with(|| json!({}));
+            let merged = merged.as_object_mut().unwrap();
+            let parameters = item.get("parameters");
+
+            for (key, operation) in item {
+                if !METHODS.contains(&key.as_str()) {
+                    // Shared parameters are moved into the operations, as other entries may define the same path
+                    if key != "parameters" {
+                        merged.entry(key.clone()).or_insert_with(|| operation.clone());
+                    }
+                    continue;
+                }
+                if merged.contains_key(key) {
+                    tracing::debug!("Leaving {} {path} of {name} out of the merged document, already defined", key.to_uppercase());
+                    continue;
+                }
+
+                let mut operation = operation.clone();
+                if let Some(operation) = operation.as_object_mut() {
+                    inherit_parameters(operation, parameters);
+                    inherit(operation, "security", security.as_ref());
+                    operation.insert("x-gateway-entry".to_string(), json!(name));
+
+                    for tag_name in operation.get("tags").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                        add_tag(&mut tags, tag_name, name, None);
+                    }
+                }
+                merged.insert(key.clone(), operation);
+            }
+        }
+
+        for (webhook, item) in document.get("webhooks").and_then(Value::as_object).into_iter().flatten() {
+            if webhooks.contains_key(webhook) {
+                tracing::debug!("Leaving webhook {webhook} of {name} out of the merged document, already defined");
+                continue;
+            }
+
+            let mut item = item.clone();
+            for (_, operation) in item.as_object_mut().into_iter().flatten().filter(|(key, _)| METHODS.contains(&key.as_str())) {
+                if let Some(operation) = operation.as_object_mut() {
+                    operation.insert("x-gateway-entry".to_string(), json!(name));
+
+                    for tag_name in operation.get("tags").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                        add_tag(&mut tags, tag_name, name, None);
+                    }
+                }
+            }
+            webhooks.insert(webhook.clone(), item);
+        }
+    }
+
+    let mut merged = json!({
+        "openapi": documents.first().and_then(|(_, _, document)| document.get("openapi")).cloned().unwrap_or(json!("3.0.3")),
+        "info": {"title": "OpenAPI Gateway", "version": env!("CARGO_PKG_VERSION")},
+        "servers": [{"url": if public_url.is_empty() { "/" } else { public_url }}],
+        "paths": paths,
+    });
+    if !components.is_empty() {
+        merged["components"] = json!(components);
+    }
+    if !tags.is_empty() {
+        merged["tags"] = json!(tags);
+    }
+    if !webhooks.is_empty() && merged["openapi"].as_str().map(|version| version.starts_with("3.1")).unwrap_or(false) {
+        merged["webhooks"] = json!(webhooks);
+    }
+
+    merged
+}
+
+/// Component names per section defined by more than one entry
+fn component_collisions(documents: &[(&str, &OpenApiFile, Value)]) -> HashMap<(String, String), usize> {
+    let mut counts = HashMap::new();
+
+    for (_, _, document) in documents {
+        for (section, values) in document.get("components").and_then(Value::as_object).into_iter().flatten() {
+            for component in values.as_object().into_iter().flatten().map(|(component, _)| component) {
+                *counts.entry((section.clone(), component.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    counts.retain(|_, count| *count > 1);
+    counts
+}
+
+/// New names of the colliding components of a document, keyed by section and name
+fn renames(name: &str, document: &Value, collisions: &HashMap<(String, String), usize>) -> HashMap<(String, String), String> {
+    // Component names may only contain `a-z A-Z 0-9 . - _`
+    let prefix: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+
+    document.get("components").and_then(Value::as_object).into_iter().flatten()
+        .flat_map(|(section, values)| {
+            values.as_object().into_iter().flatten().map(move |(component, _)| (section.clone(), component.clone()))
+        })
+        .filter(|key| collisions.contains_key(key))
+        .map(|(section, component)| {
+            let renamed = format!("{prefix}_{component}");
+            ((section, component), renamed)
+        })
+        .collect()
+}
+
+/// Points `$ref`s, discriminator mappings and security requirements to the renamed components
+fn rename_refs(value: &mut Value, renames: &HashMap<(String, String), String>) {
+    if renames.is_empty() {
+        return;
+    }
+
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => rename_ref(reference, renames),
+                    ("mapping", Value::Object(mapping)) => {
+                        // Could also be a property named `mapping`
+                        for value in mapping.values_mut() {
+                            match value {
+                                Value::String(reference) => rename_ref(reference, renames),
+                                value => rename_refs(value, renames),
+                            }
+                        }
+                    }
+                    ("security", Value::Array(requirements)) => {
+                        for requirement in requirements.iter_mut().filter_map(Value::as_object_mut) {
+                            *requirement = std::mem::take(requirement)
+                                .into_iter()
+                                .map(|(scheme, scopes)| {
+                                    let key = ("securitySchemes".to_string(), scheme);
+                                    (renames.get(&key).cloned().unwrap_or(key.1), scopes)
+                                })
+                                .collect();
+                        }
+                    }
+                    (_, value) => rename_refs(value, renames),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| rename_refs(value, renames)),
+        _ => {}
+    }
+}
+
+fn rename_ref(reference: &mut String, renames: &HashMap<(String, String), String>) {
+    let Some(pointer) = reference.strip_prefix("#/components/") else {
+        return;
+    };
+    let mut segments = pointer.splitn(3, '/');
+    let (Some(section), Some(component)) = (segments.next(), segments.next()) else {
+        return;
+    };
+
+    if let Some(renamed) = renames.get(&(section.to_string(), component.to_string())) {
+        *reference = match segments.next() {
+            Some(rest) => format!("#/components/{section}/{renamed}/{rest}"),
+            None => format!("#/components/{section}/{renamed}"),
+        };
+    }
+}
+
+/// Sets a document or path level value on an operation that does not override it
+fn inherit(operation: &mut Map<String, Value>, key: &str, value: Option<&Value>) {
+    if let Some(value) = value {
+        operation.entry(key.to_string()).or_insert_with(|| value.clone());
+    }
+}
+
+/// Adds the path level parameters the operation does not override by name and location
+fn inherit_parameters(operation: &mut Map<String, Value>, parameters: Option<&Value>) {
+    let Some(Value::Array(parameters)) = parameters else {
+        return;
+    };
+    let key = |parameter: &Value| match parameter.get("$ref") {
+        Some(reference) => (reference.clone(), Value::Null),
+        None => (parameter["name"].clone(), parameter["in"].clone()),
+    };
+
+    let Value::Array(overrides) = operation.entry("parameters").or_insert_with(|| json!([])) else {
+        return;
+    };
+    let inherited: Vec<Value> = parameters.iter()
+        .filter(|parameter| !overrides.iter().any(|other| key(other) == key(parameter)))
+        .cloned()
+        .collect();
+    overrides.splice(0..0, inherited);
+}
+
+/// Declares a tag once, listing every entry that uses it in `x-gateway-entries`
+fn add_tag(tags: &mut Vec<Value>, tag_name: &str, entry: &str, declared: Option<&mut Map<String, Value>>) {
+    let index = match tags.iter().position(|tag| tag["name"] == tag_name) {
+        Some(index) => index,
+        None => {
+            let tag = match declared {
+                Some(declared) => std::mem::take(declared),
+                None => Map::from_iter([("name".to_string(), json!(tag_name))]),
+            };
+            tags.push(Value::Object(tag));
+            tags.len() - 1
+        }
+    };
+
+    let entries = tags[index]
+        .as_object_mut()
+        .unwrap()
+        .entry("x-gateway-entries")
+        .or_insert_with(|| json!([]));
+    if let Some(entries) = entries.as_array_mut() {
+        if !entries.iter().any(|existing| existing == entry) {
+            entries.push(json!(entry));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OpenApiConfig;
+    use crate::gateway::merge::merge_openapi;
+    use crate::gateway::openapi::{parse_openapi, ContentType};
+    use crate::gateway::GatewayEntry;
+    use serde_json::json;
+
+    const USERS: &str = r##"{
+        "openapi": "3.0.3",
+        "tags": [{"name": "users", "description": "Users"}],
+        "security": [{"token": []}],
+        "paths": {"/users/{id}": {
+            "parameters": [{"name": "id", "in": "path", "required": true}],
+            "get": {"tags": ["users"], "parameters": [{"name": "id", "in": "path", "required": true, "description": "User"}, {"name": "fields", "in": "query"}], "responses": {"200": {"content": {"application/json": {"schema": {"$ref": "#/components/schemas/Error/properties/code"}}}}}}
+        }},
+        "components": {
+            "schemas": {"Error": {"properties": {"code": {"type": "integer"}}}, "User": {"type": "object"}},
+            "securitySchemes": {"token": {"type": "http", "scheme": "bearer"}}
+        }
+    }"##;
+    const ORDERS: &str = r##"openapi: 3.0.3
+servers:
+  - url: https://orders.example.com/api
+paths:
+  /orders:
+    get:
+      tags: [orders]
+      responses:
+        200:
+          content:
+            application/json:
+              schema:
+                $ref: '#/components/schemas/Error'
+components:
+  schemas:
+    Error:
+      type: string
+"##;
+
+    #[test]
+    fn merge_paths_and_prefix_colliding_components() {
+        let entries = vec![
+            entry("Users API", ContentType::JSON, USERS),
+            entry("orders", ContentType::YAML, ORDERS),
+            entry("swagger", ContentType::JSON, r#"{"swagger": "2.0", "paths": {"/legacy": {"get": {}}}}"#),
+        ];
+
+        let merged = merge_openapi(&entries, "https://gateway.example.com");
+
+        assert_eq!(json!([{"url": "https://gateway.example.com"}]), merged["servers"]);
+        assert!(merged["paths"].get("/legacy").is_none());
+
+        let users = &merged["paths"]["/users/{id}"]["get"];
+        assert_eq!("Users API", users["x-gateway-entry"]);
+        assert_eq!(json!(["id", "fields"]), json!(users["parameters"].as_array().unwrap().iter().map(|param| &param["name"]).collect::<Vec<_>>()));
+        assert_eq!("User", users["parameters"][0]["description"]);
+        assert_eq!(json!([{"token": []}]), users["security"]);
+        assert_eq!(
+            "#/components/schemas/Users_API_Error/properties/code",
+            users["responses"]["200"]["content"]["application/json"]["schema"]["$ref"]
+        );
+        assert_eq!(
+            "#/components/schemas/orders_Error",
+            merged["paths"]["/api/orders"]["get"]["responses"]["200"]["content"]["application/json"]["schema"]["$ref"]
+        );
+
+        let schemas = merged["components"]["schemas"].as_object().unwrap();
+        assert_eq!(vec!["User", "Users_API_Error", "orders_Error"], schemas.keys().collect::<Vec<_>>());
+
+        assert_eq!(
+            json!([
+                {"name": "users", "description": "Users", "x-gateway-entries": ["Users API"]},
+                {"name": "orders", "x-gateway-entries": ["orders"]},
+            ]),
+            merged["tags"]
+        );
+    }
+
+    #[test]
+    fn first_entry_wins_for_the_same_operation() {
+        let first = r#"{"openapi": "3.0.0", "paths": {"/users": {"get": {"operationId": "first"}}}}"#;
+        let second = r#"{"openapi": "3.0.0", "paths": {"/users": {"get": {"operationId": "second"}, "post": {}}}}"#;
+
+        let merged = merge_openapi(&[entry("first", ContentType::JSON, first), entry("second", ContentType::JSON, second)], "");
+
+        assert_eq!("first", merged["paths"]["/users"]["get"]["operationId"]);
+        assert_eq!("second", merged["paths"]["/users"]["post"]["x-gateway-entry"]);
+    }
+
+    #[test]
+    fn merge_webhooks_into_openapi_3_1() {
+        let petstore = entry("petstore", ContentType::YAML, include_str!("../../fixtures/petstore-3.1.yaml"));
+        let webhooks = r#"{"openapi": "3.1.0", "webhooks": {"newPet": {"post": {"operationId": "other"}}, "newOrder": {"post": {}}}}"#;
+
+        let merged = merge_openapi(&[petstore, entry("orders", ContentType::JSON, webhooks)], "");
+
+        assert_eq!("3.1.0", merged["openapi"]);
+        assert_eq!("newPet", merged["webhooks"]["newPet"]["post"]["operationId"]);
+        assert_eq!("orders", merged["webhooks"]["newOrder"]["post"]["x-gateway-entry"]);
+        assert!(merged["paths"].get("/v1/pets/{petId}").is_some());
+
+        // OpenAPI 3.0 has no webhooks
+        let petstore = entry("petstore", ContentType::YAML, include_str!("../../fixtures/petstore-3.0.yaml"));
+        let merged = merge_openapi(&[petstore, entry("orders", ContentType::JSON, webhooks)], "");
+        assert!(merged.get("webhooks").is_none());
+    }
+
+    fn entry(name: &str, content_type: ContentType, contents: &str) -> GatewayEntry {
+        let config = OpenApiConfig { name: name.to_string(), ..Default::default() };
+
+        parse_openapi(content_type, config, contents.as_bytes()).unwrap()
+    }
+}
@@ -1,12 +1,20 @@
 use crate::config::OpenApiConfig;
-use crate::gateway::{GatewayEntry, OpenApiFile, Route};
-use crate::openapi::{OpenApiV3, Parameter, Server};
+use crate::gateway::diff::ChangeLog;
+use crate::gateway::filter::filter_operations;
+use crate::gateway::lint::{lint, Diagnostic, Severity};
+use crate::gateway::{EntryStatus, GatewayEntry, OpenApiFile, Route};
+use crate::openapi::{OpenApiV3, Parameter};
+use chrono::Utc;
 use regex::{escape, Regex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::str::FromStr;
-use serde_json::{Value as JsonValue, Value};
+use serde_json::{json, Value as JsonValue};
 use serde_yaml::Value as YmlValue;
 
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum ContentType {
     JSON,
     YAML
@@ -14,121 +22,305 @@ pub enum ContentType {
 
 #[derive(thiserror::Error, Debug)]
 pub enum ParseError {
-    #[error("JSON Parse error")]
-    JsonParseError(#[from] serde_json::Error),
-    #[error("YAML Parse error")]
-    YamlParseError(#[from] serde_yaml::Error),
+    #[error("JSON parse error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("YAML parse error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("OpenAPI file must be an object")]
+    NotAnObject,
+    /// The file does not match the OpenAPI model, with the errors found by linting
+    #[error("invalid OpenAPI file: {0}")]
+    Invalid(String),
 }
+
+/// SHA-256 of a fetched file, hex encoded
+pub fn content_hash(file_buffer: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(file_buffer))
+}
+
 pub fn parse_openapi(
     content_type: ContentType,
     config: OpenApiConfig,
     file_buffer: &[u8]
 ) -> Result<GatewayEntry, ParseError> {
-    let mut buffer = file_buffer.to_vec();
-    let (content_type, mut document): (&'static str, OpenApiV3) = match content_type {
-        ContentType::JSON => {
-            let mut value: JsonValue = serde_json::from_slice(buffer.as_slice())?;
-            let value_map = value.as_object_mut().unwrap();
-            // Remove servers as it has to go through this application
-            value_map.remove("servers");
-
-            buffer = serde_json::to_vec(&value).unwrap();
-            ("application/json", serde_json::from_slice(&buffer)?)
-        }
-        ContentType::YAML => {
-            let mut value: YmlValue = serde_yaml::from_slice(buffer.as_slice())?;
-            let value_map = value.as_mapping_mut().unwrap();
-            // Remove servers as it has to go through this application
-            value_map.remove(&serde_yaml::Value::String("servers".to_string()));
-
-            buffer = serde_json::to_vec(&value).unwrap();
-
-            ("application/yaml", serde_yaml::from_slice(&buffer)?)
-        }
+    let content_hash = content_hash(file_buffer);
+    let mut value: JsonValue = match content_type {
+        ContentType::JSON => serde_json::from_slice(file_buffer)?,
+        ContentType::YAML => serde_json::to_value(serde_yaml::from_slice::<YmlValue>(file_buffer)?)?,
     };
 
-    if document.servers.is_empty() {
-        document.servers.push(Server {
-            url: "/".to_string(),
-        })
+    resolve_path_refs(&mut value);
+    let base_paths = base_paths(&value, &config.url);
+    let path_base_paths = path_base_paths(&value, &config.url);
+    // Remove servers as it has to go through this application, they are set again when the file is served
+    let value_map = value.as_object_mut().ok_or(ParseError::NotAnObject)?;
+    for key in ["servers", "host", "basePath", "schemes"] {
+        value_map.remove(key);
     }
+    for item in value_map.get_mut("paths").and_then(JsonValue::as_object_mut).into_iter().flat_map(|paths| paths.values_mut()) {
+        if let Some(item) = item.as_object_mut() {
+            item.remove("servers");
+        }
+    }
+    filter_operations(&mut value, &config);
 
-    let routes: Vec<_> = document
-        .servers
-        .iter()
-        .map(|server| {
-            let server_prefix = server.url.trim_end_matches("/");
+    let diagnostics = lint(&value);
+    for diagnostic in &diagnostics {
+        match diagnostic.severity {
+            Severity::Error => tracing::warn!("{}: {diagnostic}", config.name),
+            Severity::Warning => tracing::info!("{}: {diagnostic}", config.name),
+        }
+    }
 
-            let routes = collect_routes(&document, server_prefix);
+    let document = OpenApiV3::deserialize(&value).map_err(|err| {
+        let errors: Vec<String> = diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(Diagnostic::to_string)
+            .collect();
 
-            routes.iter()
-                .for_each(|route| {
-                    tracing::info!("Register route: {} {}", route.method, route.uri_regex.as_str())
-                });
+        match errors.is_empty() {
+            true => ParseError::Invalid(err.to_string()),
+            false => ParseError::Invalid(format!("{err}, {}", errors.join(", "))),
+        }
+    })?;
+    let buffer = serde_json::to_vec(&value)?;
+    let content_type = match content_type {
+        ContentType::JSON => "application/json",
+        ContentType::YAML => "application/yaml",
+    };
 
-            routes
-        })
-        .flatten()
-        .collect();
+    let routes = collect_routes(&document, |path| path_base_paths.get(path).unwrap_or(&base_paths));
+    routes.iter()
+        .for_each(|route| {
+            tracing::info!("Register route: {} {}", route.method, route.uri_regex.as_str())
+        });
 
     Ok(GatewayEntry {
         config,
         openapi_file: Some(OpenApiFile {
             content_type: content_type.to_string(),
-            contents: buffer.to_vec()
-
+            contents: buffer,
+            content_hash,
+            etag: None,
+            last_modified: None,
+            base_paths,
+            path_base_paths,
+            diagnostics,
         }),
         routes,
+        status: EntryStatus::succeeded(Utc::now()),
+        changes: ChangeLog::default(),
     })
 }
 
-fn collect_routes(json: &OpenApiV3, server_prefix: &str) -> Vec<Route> {
+/// Paths of the servers of an OpenAPI 3 file or the `basePath` of a Swagger 2 file, without trailing `/`.
+/// Requests are proxied with the base path, so routes are registered under it.
+pub fn base_paths(document: &JsonValue, spec_url: &str) -> Vec<String> {
+    if document.get("swagger").is_some() {
+        let base_path = document.get("basePath").and_then(JsonValue::as_str).unwrap_or("");
+        return vec![base_path.trim_end_matches('/').to_string()];
+    }
+
+    let mut base_paths = server_base_paths(document, spec_url);
+    if base_paths.is_empty() {
+        base_paths.push(String::new());
+    }
+
+    base_paths
+}
+
+/// Base paths of the path items with their own servers, keyed by path
+pub fn path_base_paths(document: &JsonValue, spec_url: &str) -> BTreeMap<String, Vec<String>> {
+    document.get("paths").and_then(JsonValue::as_object).into_iter().flatten()
+        .map(|(path, item)| (path.clone(), server_base_paths(item, spec_url)))
+        .filter(|(_, base_paths)| !base_paths.is_empty())
+        .collect()
+}
+
+/// Paths of the `servers` of a document or path item
+fn server_base_paths(value: &JsonValue, spec_url: &str) -> Vec<String> {
+    let mut base_paths: Vec<String> = vec![];
+    let servers = value.get("servers").and_then(JsonValue::as_array);
+    for server in servers.into_iter().flatten() {
+        let Some(mut url) = server.get("url").and_then(JsonValue::as_str).map(str::to_string) else {
+            continue;
+        };
+        let variables = server.get("variables").and_then(JsonValue::as_object);
+        for (name, variable) in variables.into_iter().flatten() {
+            if let Some(default) = variable.get("default").and_then(JsonValue::as_str) {
+                url = url.replace(&format!("{{{name}}}"), default);
+            }
+        }
+
+        let base_path = server_path(&url, spec_url).trim_end_matches('/').to_string();
+        if !base_paths.contains(&base_path) {
+            base_paths.push(base_path);
+        }
+    }
+
+    base_paths
+}
+
+/// Replaces path items that are a local `$ref`, e.g. to `#/components/pathItems/Users`, with their target.
+/// Keys next to the `$ref` win. `$ref`s to other files are already bundled.
+fn resolve_path_refs(document: &mut JsonValue) {
+    let paths: Vec<String> = document.get("paths").and_then(JsonValue::as_object).into_iter().flatten()
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    for path in paths {
+        // Path items referring to path items, but not endlessly
+        for _ in 0..8 {
+            let item = document.get("paths").and_then(|paths| paths.get(&path));
+            let Some(reference) = item.and_then(|item| item.get("$ref")).and_then(JsonValue::as_str).map(str::to_string) else {
+                break;
+            };
+            let target = reference.strip_prefix('#')
+                .and_then(|pointer| document.pointer(pointer))
+                .and_then(JsonValue::as_object)
+                .cloned();
+            if target.is_none() {
+                tracing::warn!("Path item {reference} of {path} not found");
+            }
+
+            if let Some(item) = document.get_mut("paths").and_then(|paths| paths.get_mut(&path)).and_then(JsonValue::as_object_mut) {
+                item.remove("$ref");
+                for (key, value) in target.into_iter().flatten() {
+                    item.entry(key).or_insert(value);
+                }
+            }
+        }
+    }
+}
+
+/// Points the servers of a parsed OpenAPI file at the gateway, keeping the base paths
+pub fn set_servers(document: &mut JsonValue, public_url: &str, base_paths: &[String], path_base_paths: &BTreeMap<String, Vec<String>>) {
+    let Some(document) = document.as_object_mut() else {
+        return;
+    };
+
+    if document.contains_key("swagger") {
+        let (scheme, rest) = public_url.split_once("://").unwrap_or(("", ""));
+        let (host, prefix) = rest.find('/').map(|index| rest.split_at(index)).unwrap_or((rest, ""));
+        let base_path = format!("{prefix}{}", base_paths.first().map(String::as_str).unwrap_or(""));
+
+        if !host.is_empty() {
+            document.insert("host".to_string(), json!(host));
+            document.insert("schemes".to_string(), json!([scheme]));
+        }
+        document.insert("basePath".to_string(), json!(if base_path.is_empty() { "/" } else { &base_path }));
+        return;
+    }
+
+    let servers = |base_paths: &[String]| -> Vec<JsonValue> {
+        base_paths.iter()
+            .map(|base_path| match format!("{public_url}{base_path}") {
+                url if url.is_empty() => json!({"url": "/"}),
+                url => json!({"url": url}),
+            })
+            .collect()
+    };
+    document.insert("servers".to_string(), json!(servers(base_paths)));
+
+    let paths = document.get_mut("paths").and_then(JsonValue::as_object_mut);
+    for (path, item) in paths.into_iter().flatten() {
+        if let (Some(base_paths), Some(item)) = (path_base_paths.get(path), item.as_object_mut()) {
+            item.insert("servers".to_string(), json!(servers(base_paths)));
+        }
+    }
+}
+
+/// Path of a server URL, relative URLs are resolved against the URL of the OpenAPI file
+fn server_path(url: &str, spec_url: &str) -> String {
+    if url.contains("://") {
+        return url_path(url).to_string();
+    }
+    if url.starts_with('/') {
+        return url.to_string();
+    }
+
+    let spec_path = url_path(spec_url);
+    let directory = spec_path.rsplit_once('/').map(|(directory, _)| directory).unwrap_or("");
+    match url.trim_start_matches("./") {
+        "" | "." => directory.to_string(),
+        relative => format!("{directory}/{relative}"),
+    }
+}
+
+/// Path of an absolute URL
+fn url_path(url: &str) -> &str {
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or(""),
+        None => "",
+    }
+}
+
+/// Routes of every operation under each base path of its path item
+fn collect_routes<'a>(json: &OpenApiV3, base_paths: impl Fn(&str) -> &'a [String]) -> Vec<Route> {
     json.paths
         .iter()
-        .map(|path| {
-            let path_uri = format!("{}{}", server_prefix, path.0);
-
+        .flat_map(|path| base_paths(path.0).iter().map(move |base_path| (format!("{}{}", base_path, path.0), path)))
+        .flat_map(|(path_uri, path)| {
             path.1
                 .methods
                 .iter()
-                .map(|method| {
-                    let parameters = method.1
-                        .parameters
-                        .as_ref()
-                        .unwrap_or(&path.1.parameters);
+                .filter_map(|method| {
+                    let parameters = json.parameters(path.1, method.1);
 
-                    let regex = regex_from_route(&path_uri, parameters);
+                    let regex = match regex_from_route(&path_uri, &parameters) {
+                        Ok(regex) => regex,
+                        Err(err) => {
+                            tracing::warn!("Not routing {} {path_uri}: {err}", method.0.to_uppercase());
+                            return None;
+                        }
+                    };
 
-                    Route {
+                    Some(Route {
                         uri_regex: regex,
+                        template: path_uri.clone(),
                         method: method.0.clone(),
-                        path_parameters: parameters.iter()
+                        operation_id: method.1.operation_id.clone(),
+                        path_parameters: parameters.into_iter()
                             .filter(|param| param.in_type == "path")
-                            .map(|param| param.clone())
-                            .collect::<Vec<_>>()
-                    }
+                            .collect::<Vec<_>>(),
+                        deprecated: method.1.deprecated,
+                        sunset: method.1.sunset.as_ref().and_then(|sunset| sunset.as_str()).map(str::to_string),
+                    })
                 })
                 .collect::<Vec<_>>()
         })
-        .flatten()
         .collect()
 }
 
-fn regex_from_route(url: &str, parameters: &Vec<Parameter>) -> Regex {
-    let pattern = parameters
-        .iter()
-        .filter(|param| param.in_type == "path")
-        .fold(escape(url), |pattern, param| {
-            pattern.replace(&format!("\\{{{}\\}}", param.name.replace("-", "\\-")), "[^/]*")
-        });
+/// Declared path parameters match any segment, the rest of the path literally. Fails on paths too long for a regex.
+fn regex_from_route(url: &str, parameters: &[Parameter]) -> Result<Regex, regex::Error> {
+    let mut pattern = String::from("^");
+    let mut rest = url;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        let name = &rest[start + 1..end];
+
+        pattern.push_str(&escape(&rest[..start]));
+        match parameters.iter().any(|param| param.in_type == "path" && param.name == name) {
+            true => pattern.push_str("[^/]*"),
+            false => pattern.push_str(&escape(&rest[start..=end])),
+        }
+        rest = &rest[end + 1..];
+    }
+    pattern.push_str(&escape(rest));
+    pattern.push('$');
 
-    Regex::from_str(&format!("^{}$", &pattern)).unwrap()
+    Regex::from_str(&pattern)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::gateway::openapi::regex_from_route;
+    use crate::config::OpenApiConfig;
+    use crate::gateway::openapi::{base_paths, parse_openapi, regex_from_route, ContentType};
     use crate::openapi::Parameter;
+    use serde_json::json;
 
     #[test]
     fn test_valid_regex() {
@@ -136,11 +328,12 @@ mod tests {
 
         let regex = regex_from_route(
             url,
-            &vec![Parameter {
+            &[Parameter {
                 name: "user_id".to_string(),
                 in_type: "path".to_string(),
+                required: true,
             }],
-        );
+        ).unwrap();
 
         eprintln!("^{}$", regex.as_str());
         assert_eq!(regex.as_str(), "^/v1/users/[^/]*$");
@@ -152,11 +345,12 @@ mod tests {
 
         let regex = regex_from_route(
             url,
-            &vec![Parameter {
+            &[Parameter {
                 name: "user_id".to_string(),
                 in_type: "path".to_string(),
+                required: true,
             }],
-        );
+        ).unwrap();
 
         assert!(regex.is_match("/v1/users/123"));
     }
@@ -167,12 +361,208 @@ mod tests {
 
         let regex = regex_from_route(
             url,
-            &vec![Parameter {
+            &[Parameter {
                 name: "user_id".to_string(),
                 in_type: "path".to_string(),
+                required: true,
             }],
-        );
+        ).unwrap();
 
         assert!(regex.is_match("/v1/users/123-suffix/subroute"));
     }
+
+    #[test]
+    fn base_paths_of_servers() {
+        let spec_url = "https://users.example.com/specs/openapi.json";
+        let servers = json!({"openapi": "3.0.0", "servers": [
+            {"url": "https://{environment}.example.com/{version}/", "variables": {"environment": {"default": "api"}, "version": {"default": "v1"}}},
+            {"url": "/v1"},
+            {"url": "internal"},
+        ]});
+
+        assert_eq!(vec!["/v1", "/specs/internal"], base_paths(&servers, spec_url));
+        assert_eq!(vec![""], base_paths(&json!({"openapi": "3.0.0", "servers": [{"url": "https://users.example.com"}]}), spec_url));
+        assert_eq!(vec!["/api"], base_paths(&json!({"swagger": "2.0", "basePath": "/api"}), spec_url));
+        assert_eq!(vec!["/v1"], base_paths(&json!({"openapi": "3.0.0", "servers": [{"url": "v1"}]}), ""));
+    }
+
+    #[test]
+    fn publish_with_gateway_as_server() {
+        let config = OpenApiConfig { name: "users".to_string(), url: "https://users.example.com/openapi.json".to_string(), ..Default::default() };
+        let openapi = r#"{"openapi": "3.0.0", "servers": [{"url": "https://users.example.com/v1"}], "paths": {"/users": {"get": {}}}}"#;
+        let swagger = r#"{"swagger": "2.0", "host": "users.example.com", "basePath": "/v2", "schemes": ["https"], "paths": {"/users": {"get": {}}}}"#;
+
+        let entry = parse_openapi(ContentType::JSON, config.clone(), openapi.as_bytes()).unwrap();
+        assert_eq!("/v1/users", entry.routes[0].template);
+        let published: serde_json::Value = serde_json::from_slice(&entry.openapi_file.unwrap().published("https://gateway.example.com")).unwrap();
+        assert_eq!(json!([{"url": "https://gateway.example.com/v1"}]), published["servers"]);
+
+        let entry = parse_openapi(ContentType::JSON, config, swagger.as_bytes()).unwrap();
+        assert_eq!("/v2/users", entry.routes[0].template);
+        let file = entry.openapi_file.unwrap();
+        let published: serde_json::Value = serde_json::from_slice(&file.published("https://gateway.example.com/prefix")).unwrap();
+        assert_eq!("gateway.example.com", published["host"]);
+        assert_eq!("/prefix/v2", published["basePath"]);
+        assert_eq!(json!(["https"]), published["schemes"]);
+
+        let published: serde_json::Value = serde_json::from_slice(&file.published("")).unwrap();
+        assert!(published.get("host").is_none());
+    }
+
+    #[test]
+    fn merge_path_and_operation_parameters() {
+        let openapi = r##"{"openapi": "3.0.0", "paths": {"/users/{id}/orders/{order-id}": {
+            "parameters": [{"$ref": "#/components/parameters/id"}, {"name": "limit", "in": "query"}],
+            "get": {"parameters": [{"name": "order-id", "in": "path", "required": true}, {"name": "limit", "in": "query", "required": true}]}
+        }}, "components": {"parameters": {"id": {"$ref": "#/components/parameters/user~1id"}, "user/id": {"name": "id", "in": "path", "required": true}}}}"##;
+        let swagger = r##"{"swagger": "2.0", "paths": {"/users/{id}": {"get": {"parameters": [{"$ref": "#/parameters/id"}]}}},
+            "parameters": {"id": {"name": "id", "in": "path", "required": true}}}"##;
+
+        let entry = parse_openapi(ContentType::JSON, OpenApiConfig::default(), openapi.as_bytes()).unwrap();
+        let route = &entry.routes[0];
+        assert_eq!(vec!["id", "order-id"], route.path_parameters.iter().map(|param| param.name.as_str()).collect::<Vec<_>>());
+        assert!(route.uri_regex.is_match("/users/1/orders/2"));
+
+        let document = entry.openapi_file.unwrap().document().unwrap();
+        let path = &document.paths["/users/{id}/orders/{order-id}"];
+        let limit = document.parameters(path, &path.methods["get"]).into_iter().find(|param| param.name == "limit").unwrap();
+        assert!(limit.required);
+
+        let entry = parse_openapi(ContentType::JSON, OpenApiConfig::default(), swagger.as_bytes()).unwrap();
+        assert!(entry.routes[0].uri_regex.is_match("/users/1"));
+    }
+
+    #[test]
+    fn ignore_extensions_and_invalid_responses() {
+        let openapi = r#"{"openapi": "3.0.0", "paths": {"/users": {"post": {
+            "requestBody": {"content": "invalid"},
+            "responses": {"x-foo": "bar", "x-codes": {"content": {}}, "200": {}, "404": "invalid", "500": {"content": []}}
+        }}}, "components": {"schemas": [], "responses": {"Invalid": 1}}}"#;
+
+        let entry = parse_openapi(ContentType::JSON, OpenApiConfig::default(), openapi.as_bytes()).unwrap();
+        assert_eq!(1, entry.routes.len());
+
+        let document = entry.openapi_file.unwrap().document().unwrap();
+        let operation = &document.paths["/users"].methods["post"];
+        assert_eq!(vec!["200"], operation.responses.keys().collect::<Vec<_>>());
+        assert!(operation.request_body.is_none());
+        assert!(document.components.responses.is_empty());
+    }
+
+    #[test]
+    fn path_items_with_refs_and_servers() {
+        let config = OpenApiConfig { name: "users".to_string(), url: "https://users.example.com/openapi.json".to_string(), ..Default::default() };
+        let openapi = r##"{"openapi": "3.1.0", "servers": [{"url": "/v1"}], "paths": {
+            "/users": {"summary": "Users", "description": "All users", "x-owner": "team", "get": {}},
+            "/orders": {"$ref": "#/components/pathItems/Orders", "summary": "Orders"},
+            "/legacy": {"servers": [{"url": "https://legacy.example.com/old"}, {"url": "/v0"}], "get": {}}
+        }, "components": {"pathItems": {"Orders": {"summary": "Ignored", "post": {}}}}}"##;
+
+        let entry = parse_openapi(ContentType::JSON, config, openapi.as_bytes()).unwrap();
+        let routes: Vec<(&str, &str)> = entry.routes.iter().map(|route| (route.method.as_str(), route.template.as_str())).collect();
+        assert_eq!(vec![("get", "/old/legacy"), ("get", "/v0/legacy"), ("post", "/v1/orders"), ("get", "/v1/users")], routes);
+
+        let published: serde_json::Value = serde_json::from_slice(&entry.openapi_file.unwrap().published("https://gateway.example.com")).unwrap();
+        assert_eq!("Orders", published["paths"]["/orders"]["summary"]);
+        assert!(published["paths"]["/orders"].get("$ref").is_none());
+        assert_eq!(json!([{"url": "https://gateway.example.com/v1"}]), published["servers"]);
+        assert_eq!(
+            json!([{"url": "https://gateway.example.com/old"}, {"url": "https://gateway.example.com/v0"}]),
+            published["paths"]["/legacy"]["servers"]
+        );
+        assert!(published["paths"]["/users"].get("servers").is_none());
+    }
+
+    #[test]
+    fn parse_petstore_fixtures() {
+        let v3_0 = include_str!("../../fixtures/petstore-3.0.yaml");
+        let v3_1 = include_str!("../../fixtures/petstore-3.1.yaml");
+
+        for fixture in [v3_0, v3_1] {
+            let entry = parse_openapi(ContentType::YAML, OpenApiConfig::default(), fixture.as_bytes()).unwrap();
+            let routes: Vec<(&str, &str)> = entry.routes.iter().map(|route| (route.method.as_str(), route.template.as_str())).collect();
+
+            assert!(routes.contains(&("get", "/v1/pets")));
+            assert!(routes.contains(&("get", "/v1/pets/{petId}")));
+            assert!(entry.routes.iter().any(|route| route.uri_regex.is_match("/v1/pets/1")));
+        }
+
+        let entry = parse_openapi(ContentType::YAML, OpenApiConfig::default(), v3_1.as_bytes()).unwrap();
+        assert_eq!(2, entry.routes.len());
+        let document: serde_json::Value = serde_json::from_slice(&entry.openapi_file.unwrap().contents).unwrap();
+        assert!(document["webhooks"].get("newPet").is_some());
+        assert!(document["webhooks"].get("internalAudit").is_none());
+        assert_eq!(json!(["string", "null"]), document["components"]["schemas"]["Pet"]["properties"]["tag"]["type"]);
+
+        // Paths are optional since 3.1
+        let webhooks_only = r#"{"openapi": "3.1.0", "webhooks": {"newPet": {"post": {}}}}"#;
+        let entry = parse_openapi(ContentType::JSON, OpenApiConfig::default(), webhooks_only.as_bytes()).unwrap();
+        assert!(entry.routes.is_empty());
+    }
+
+    #[test]
+    fn parse_arbitrary_input_without_panicking() {
+        let fixtures = [
+            include_str!("../../fixtures/petstore-3.0.yaml").to_string(),
+            include_str!("../../fixtures/petstore-3.1.yaml").to_string(),
+            r##"{"swagger": "2.0", "basePath": "/v2", "paths": {"/users/{id}": {"parameters": [{"$ref": "#/parameters/id"}], "get": {}}}, "parameters": {"id": {"name": "id", "in": "path"}}}"##.to_string(),
+        ];
+        let not_openapi = [
+            "null", "[]", "1", r#""paths""#, r#"{"paths": []}"#, r#"{"paths": {"/a": null, "/b": {"get": null}}}"#,
+            r#"{"servers": 1, "paths": {"/a": {"servers": "x", "$ref": 1, "parameters": {}}}}"#,
+            r##"{"paths": {"/{a": {"$ref": "#/paths/~1{a"}, "/{b}}": {"get": {"parameters": [{"$ref": "#"}, {"$ref": "#/x"}]}}}}"##,
+            r#"{"openapi": "3.1.0", "webhooks": [], "components": 5}"#,
+            r#"{"swagger": "2.0", "basePath": 1, "paths": {"/": {"get": {"operationId": 1, "x-internal": "yes"}}}}"#,
+        ];
+
+        // Deterministic xorshift, the same mutations on every run
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+
+        for input in not_openapi {
+            for content_type in [ContentType::JSON, ContentType::YAML] {
+                let _ = parse_openapi(content_type, OpenApiConfig::default(), input.as_bytes());
+            }
+        }
+        for _ in 0..2000 {
+            let mut bytes = fixtures[next() % fixtures.len()].clone().into_bytes();
+            for _ in 0..=next() % 8 {
+                let index = next() % bytes.len().max(1);
+                match next() % 4 {
+                    0 if index < bytes.len() => bytes[index] = next() as u8,
+                    1 if index < bytes.len() => {
+                        bytes.remove(index);
+                    }
+                    2 => bytes.insert(index.min(bytes.len()), b"{}[]:,\"-#/~ \n"[next() % 13]),
+                    _ => bytes.truncate(index),
+                }
+            }
+
+            for content_type in [ContentType::JSON, ContentType::YAML] {
+                let _ = parse_openapi(content_type, OpenApiConfig::default(), &bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn errors_explain_what_is_wrong() {
+        let err = parse_openapi(ContentType::JSON, OpenApiConfig::default(), b"[]").unwrap_err();
+        assert_eq!("OpenAPI file must be an object", err.to_string());
+
+        let err = parse_openapi(ContentType::JSON, OpenApiConfig::default(), b"{\"paths\": ").unwrap_err();
+        assert!(err.to_string().starts_with("JSON parse error: EOF while parsing"));
+
+        let invalid = r#"{"paths": {"/users": {"get": {"parameters": [{"in": "query"}]}}}}"#;
+        let err = parse_openapi(ContentType::JSON, OpenApiConfig::default(), invalid.as_bytes()).unwrap_err();
+        assert!(err.to_string().ends_with("error at /paths/~1users/get/parameters/0: parameter must have a name and in"));
+
+        let duplicate = r#"{"paths": {"/users": {"get": {"operationId": "users"}, "post": {"operationId": "users"}}}}"#;
+        let entry = parse_openapi(ContentType::JSON, OpenApiConfig::default(), duplicate.as_bytes()).unwrap();
+        assert_eq!("/paths/~1users/post/operationId", entry.openapi_file.unwrap().diagnostics[0].pointer);
+    }
 }
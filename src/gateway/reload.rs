@@ -0,0 +1,617 @@
+use crate::config::{OpenApiConfig, ReloadConfig};
+use crate::gateway::cache::{Source, SpecCache};
+use crate::gateway::{EntryStatus, FileVersion, GatewayEntry};
+use crate::web::HttpClient;
+use crate::{fetch_entry_if_modified, FetchError, RwConfig, RwGatewayEntries};
+use chrono::{DateTime, Utc};
+use cron_parser::parse;
+use futures::future::join_all;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::time::{sleep, timeout, Duration};
+
+/// Longest the reload loop sleeps before looking at the schedules again
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+pub type HttpReloader = Reloader<HttpFetcher, SystemClock>;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub enum Fetched {
+    Modified(Box<GatewayEntry>, Source),
+    /// The file equals the previous version, only its validators may have changed
+    NotModified(FileVersion),
+}
+
+pub trait Fetcher: Send + Sync {
+    fn fetch(&self, config: &OpenApiConfig, previous: Option<&FileVersion>) -> impl Future<Output = Result<Fetched, FetchError>> + Send;
+}
+
+pub struct HttpFetcher {
+    client: HttpClient,
+}
+
+impl HttpFetcher {
+    pub fn new(client: HttpClient) -> HttpFetcher {
+        HttpFetcher { client }
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    async fn fetch(&self, config: &OpenApiConfig, previous: Option<&FileVersion>) -> Result<Fetched, FetchError> {
+        fetch_entry_if_modified(&self.client, config, previous).await
+    }
+}
+
+/// Fetches OpenAPI files concurrently, failing entries keep their last known good file and back off
+pub struct Reloader<F: Fetcher, C: Clock> {
+    fetcher: F,
+    clock: C,
+    cache: Option<SpecCache>,
+    /// Paths routed before the proxied routes, fetched entries are checked against them
+    reserved_paths: Vec<String>,
+}
+
+impl<F: Fetcher, C: Clock> Reloader<F, C> {
+    pub fn new(fetcher: F, clock: C, cache: Option<SpecCache>) -> Reloader<F, C> {
+        Reloader { fetcher, clock, cache, reserved_paths: vec![] }
+    }
+
+    pub fn with_reserved_paths(self, reserved_paths: Vec<String>) -> Reloader<F, C> {
+        Reloader { reserved_paths, ..self }
+    }
+
+    /// Fetches all entries, entries that fail to load fall back to the cache or are kept without OpenAPI file
+    pub async fn load(&self, configs: &[OpenApiConfig], settings: &ReloadConfig) -> Vec<GatewayEntry> {
+        join_all(configs.iter().map(|config| async move {
+            let result = self.fetch(config, None, settings).await;
+
+            let mut entry = match &result {
+                Ok(_) => None,
+                Err(_) => self.load_cached(config).await,
+            }.unwrap_or_else(|| GatewayEntry::unloaded(config.clone()));
+            self.apply(&mut entry, result, self.clock.now(), settings);
+
+            entry
+        })).await
+    }
+
+    /// A scheduled reload of the due entries, entries that are backing off are skipped
+    pub async fn reload(&self, entries: &RwGatewayEntries, settings: &ReloadConfig, due: &[OpenApiConfig]) {
+        let now = self.clock.now();
+
+        self.reload_where(entries, settings, |entry| due.contains(&entry.config) && entry.status.is_due(now)).await
+    }
+
+    /// Fetches the entries matching the filter again
+    pub async fn reload_where(&self, entries: &RwGatewayEntries, settings: &ReloadConfig, filter: impl Fn(&GatewayEntry) -> bool) {
+        let due: Vec<(OpenApiConfig, Option<FileVersion>)> = entries.read().await
+            .iter()
+            .filter(|entry| filter(entry))
+            .map(|entry| (entry.config.clone(), entry.openapi_file.as_ref().map(|file| file.version())))
+            .collect();
+
+        if due.is_empty() {
+            return;
+        }
+
+        tracing::info!("Start collecting OpenAPI files");
+        let results = join_all(due.iter().map(|(config, previous)| self.fetch(config, previous.as_ref(), settings))).await;
+        let now = self.clock.now();
+
+        let mut entries = entries.write().await;
+        // Entries may have been changed through the admin API while fetching
+        for ((config, _), result) in due.iter().zip(results) {
+            if let Some(entry) = entries.iter_mut().find(|entry| entry.config == *config) {
+                self.apply(entry, result, now, settings);
+            }
+        }
+    }
+
+    /// Reloads every entry on its `reload_cron`, schedules are read on every iteration as they may change
+    pub async fn run(self: Arc<Self>, config: RwConfig, entries: RwGatewayEntries) {
+        let mut since = self.clock.now();
+
+        loop {
+            let (global_cron, settings) = {
+                let config = config.read().await;
+                (config.reload_cron.clone(), config.reload.clone())
+            };
+            let now = self.clock.now();
+            let (due, next) = schedule(&entries.read().await, &global_cron, since, now);
+
+            if due.is_empty() {
+                // Wake up regularly, entries and their schedules may have changed meanwhile
+                let wait = next.and_then(|next| (next - now).to_std().ok()).unwrap_or(MAX_WAIT);
+                sleep(wait.min(MAX_WAIT)).await;
+                continue;
+            }
+
+            self.reload(&entries, &settings, &due).await;
+            since = now;
+        }
+    }
+
+    /// Reloads all entries on SIGUSR1, regardless of their schedule and backoff
+    #[cfg(unix)]
+    pub async fn reload_on_signal(self: Arc<Self>, config: RwConfig, entries: RwGatewayEntries) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut user_defined = signal(SignalKind::user_defined1()).unwrap();
+        while user_defined.recv().await.is_some() {
+            tracing::info!("Received SIGUSR1, reloading all entries");
+            let settings = config.read().await.reload.clone();
+            self.reload_where(&entries, &settings, |_| true).await;
+        }
+    }
+
+    async fn fetch(&self, config: &OpenApiConfig, previous: Option<&FileVersion>, settings: &ReloadConfig) -> Result<Fetched, FetchError> {
+        let fetch_timeout = Duration::from_secs(settings.fetch_timeout_secs);
+
+        let result = timeout(fetch_timeout, self.fetcher.fetch(config, previous))
+            .await
+            .unwrap_or(Err(FetchError::Timeout(fetch_timeout)));
+
+        if let (Some(cache), Ok(Fetched::Modified(entry, source))) = (&self.cache, &result) {
+            if let Err(err) = cache.store(entry, source, self.clock.now()).await {
+                tracing::warn!("Caching {} failed: {err}", config.name);
+            }
+        }
+
+        result
+    }
+
+    async fn load_cached(&self, config: &OpenApiConfig) -> Option<GatewayEntry> {
+        match self.cache.as_ref()?.load(config).await {
+            Ok(Some(entry)) => {
+                tracing::info!("Loaded {} from cache, fetched at {:?}", config.name, entry.status.last_success);
+                Some(entry)
+            }
+            Ok(None) => None,
+            Err(err) => {
+                tracing::warn!("Loading {} from cache failed: {err}", config.name);
+                None
+            }
+        }
+    }
+
+    fn apply(&self, entry: &mut GatewayEntry, result: Result<Fetched, FetchError>, now: DateTime<Utc>, settings: &ReloadConfig) {
+        match result {
+            Ok(Fetched::Modified(mut fetched, _)) => {
+                fetched.status = EntryStatus::succeeded(now);
+                entry.update(*fetched, now);
+                entry.warn_on_shadowed_routes(&self.reserved_paths);
+            }
+            Ok(Fetched::NotModified(version)) => {
+                if let Some(file) = entry.openapi_file.as_mut() {
+                    file.set_version(version);
+                }
+                // Breaking changes held back were reverted upstream
+                entry.changes.pending = None;
+                entry.status = EntryStatus::succeeded(now);
+            }
+            Err(err) => {
+                let backoff = backoff(entry.status.consecutive_failures + 1, settings);
+                tracing::warn!("Fetching {} failed, retrying in {}s: {err}", entry.config.name, backoff.num_seconds());
+
+                entry.status.record_failure(err.to_string(), now, backoff);
+            }
+        }
+    }
+}
+
+/// Entries of which a scheduled reload passed between `since` and `now`, and the next scheduled reload
+pub fn schedule(entries: &[GatewayEntry], global_cron: &str, since: DateTime<Utc>, now: DateTime<Utc>) -> (Vec<OpenApiConfig>, Option<DateTime<Utc>>) {
+    let scheduled: Vec<(&OpenApiConfig, DateTime<Utc>)> = entries.iter()
+        .filter(|entry| entry.config.reload)
+        .filter_map(|entry| {
+            let cron = entry.config.reload_cron.as_deref().unwrap_or(global_cron);
+            parse(cron, &since).ok().map(|next| (&entry.config, next))
+        })
+        .collect();
+
+    (
+        scheduled.iter().filter(|(_, next)| *next <= now).map(|(config, _)| (*config).clone()).collect(),
+        scheduled.iter().map(|(_, next)| *next).min(),
+    )
+}
+
+/// Doubles the initial backoff on every consecutive failure, up to the maximum
+pub fn backoff(failures: u32, settings: &ReloadConfig) -> chrono::Duration {
+    let secs = settings.backoff_initial_secs
+        .saturating_mul(2u64.saturating_pow(failures.saturating_sub(1)))
+        .min(settings.backoff_max_secs);
+
+    chrono::Duration::seconds(secs as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, OpenApiConfig, ReloadConfig};
+    use crate::gateway::cache::{Source, SpecCache};
+    use crate::gateway::openapi::ContentType;
+    use crate::gateway::reload::{backoff, schedule, Clock, Fetched, Fetcher, HttpFetcher, Reloader};
+    use crate::gateway::{EntryStatus, FileVersion, GatewayEntry, OpenApiFile};
+    use crate::web::HttpClient;
+    use crate::{FetchError, RwGatewayEntries};
+    use axum::http::header::{ETAG, IF_NONE_MATCH};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::RwLock;
+    use tokio::time::Instant;
+
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<DateTime<Utc>>>);
+
+    impl MockClock {
+        fn new() -> MockClock {
+            MockClock(Arc::new(Mutex::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())))
+        }
+
+        fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    /// Follows the paused tokio time, so the sleeps of the reload loop move it forward
+    struct PausedClock {
+        start: DateTime<Utc>,
+        instant: Instant,
+    }
+
+    impl Clock for PausedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.start + Duration::from_std(self.instant.elapsed()).unwrap()
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Outcome {
+        Succeed(&'static str),
+        Fail,
+        Delay(u64),
+        Hang,
+    }
+
+    /// Returns a scripted outcome per entry name and counts the fetches
+    #[derive(Clone, Default)]
+    struct FakeFetcher {
+        outcomes: Arc<Mutex<HashMap<String, Outcome>>>,
+        fetches: Arc<Mutex<HashMap<String, usize>>>,
+    }
+
+    impl FakeFetcher {
+        fn set(&self, name: &str, outcome: Outcome) {
+            self.outcomes.lock().unwrap().insert(name.to_string(), outcome);
+        }
+
+        fn fetches(&self, name: &str) -> usize {
+            self.fetches.lock().unwrap().get(name).copied().unwrap_or(0)
+        }
+    }
+
+    impl Fetcher for FakeFetcher {
+        async fn fetch(&self, config: &OpenApiConfig, previous: Option<&FileVersion>) -> Result<Fetched, FetchError> {
+            *self.fetches.lock().unwrap().entry(config.name.clone()).or_default() += 1;
+            let outcome = self.outcomes.lock().unwrap()[&config.name];
+
+            match outcome {
+                Outcome::Succeed(hash) if previous.map(|previous| previous.content_hash == hash).unwrap_or(false) => {
+                    Ok(Fetched::NotModified(previous.unwrap().clone()))
+                }
+                Outcome::Succeed(hash) => Ok(Fetched::Modified(Box::new(loaded(config.clone(), hash)), source())),
+                Outcome::Fail => Err(FetchError::UnknownError(Box::new(std::io::Error::other("unreachable")))),
+                Outcome::Delay(secs) => {
+                    tokio::time::sleep(std::time::Duration::from_secs(secs)).await;
+                    Ok(Fetched::Modified(Box::new(loaded(config.clone(), "delayed")), source()))
+                }
+                Outcome::Hang => std::future::pending().await,
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fetches_concurrently_with_timeout() {
+        let (fetcher, clock) = (FakeFetcher::default(), MockClock::new());
+        fetcher.set("first", Outcome::Delay(5));
+        fetcher.set("second", Outcome::Delay(5));
+        fetcher.set("hanging", Outcome::Hang);
+        let reloader = Reloader::new(fetcher.clone(), clock, None);
+
+        let start = Instant::now();
+        let entries = reloader.load(&[config("first"), config("second"), config("hanging")], &settings()).await;
+
+        // Both delayed fetches and the timeout run in parallel
+        assert_eq!(10, start.elapsed().as_secs());
+        assert!(entries[0].is_loaded());
+        assert!(entries[1].is_loaded());
+        assert!(!entries[2].is_loaded());
+        assert_eq!(Some("Timeout after 10s".to_string()), entries[2].status.last_error);
+    }
+
+    #[tokio::test]
+    async fn keeps_last_known_good_file_while_failing() {
+        let (fetcher, clock) = (FakeFetcher::default(), MockClock::new());
+        fetcher.set("users", Outcome::Succeed("v1"));
+        let reloader = Reloader::new(fetcher.clone(), clock.clone(), None);
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(reloader.load(&[config("users")], &settings()).await));
+        let loaded_at = clock.now();
+
+        fetcher.set("users", Outcome::Fail);
+        clock.advance(Duration::minutes(1));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+
+        {
+            let entries = entries.read().await;
+            assert_eq!("v1", entries[0].openapi_file.as_ref().unwrap().content_hash);
+            assert!(entries[0].is_stale());
+            assert_eq!(Some(loaded_at), entries[0].status.last_success);
+            assert_eq!(Some(clock.now()), entries[0].status.failing_since);
+        }
+
+        fetcher.set("users", Outcome::Succeed("v2"));
+        clock.advance(Duration::minutes(1));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+
+        let entries = entries.read().await;
+        assert_eq!("v2", entries[0].openapi_file.as_ref().unwrap().content_hash);
+        assert!(!entries[0].is_stale());
+        assert_eq!(None, entries[0].status.failing_since);
+        assert_eq!(Some(clock.now()), entries[0].status.last_success);
+    }
+
+    #[tokio::test]
+    async fn backs_off_exponentially_on_failures() {
+        let (fetcher, clock) = (FakeFetcher::default(), MockClock::new());
+        fetcher.set("users", Outcome::Fail);
+        let reloader = Reloader::new(fetcher.clone(), clock.clone(), None);
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(reloader.load(&[config("users")], &settings()).await));
+        assert_eq!(1, fetcher.fetches("users"));
+
+        // Initial backoff of 60 seconds
+        clock.advance(Duration::seconds(59));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+        assert_eq!(1, fetcher.fetches("users"));
+        clock.advance(Duration::seconds(1));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+        assert_eq!(2, fetcher.fetches("users"));
+
+        // Doubled after the second failure
+        clock.advance(Duration::seconds(119));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+        assert_eq!(2, fetcher.fetches("users"));
+        clock.advance(Duration::seconds(1));
+        fetcher.set("users", Outcome::Succeed("v1"));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+        assert_eq!(3, fetcher.fetches("users"));
+
+        // A success resets the backoff
+        let status = entries.read().await[0].status.clone();
+        assert_eq!(0, status.consecutive_failures);
+        assert!(status.is_due(clock.now()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_reloads_on_schedule_and_backs_off() {
+        let fetcher = FakeFetcher::default();
+        fetcher.set("users", Outcome::Succeed("v1"));
+        fetcher.set("hourly", Outcome::Succeed("v1"));
+        fetcher.set("failing", Outcome::Fail);
+        let clock = PausedClock { start: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(), instant: Instant::now() };
+        let reloader = Arc::new(Reloader::new(fetcher.clone(), clock, None));
+        let hourly = OpenApiConfig { reload_cron: Some("0 * * * *".to_string()), ..config("hourly") };
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(
+            reloader.load(&[config("users"), hourly, config("failing")], &settings()).await,
+        ));
+        let gateway_config: Config = toml::from_str("reload_cron = \"* * * * *\"\nopenapi_urls = []\n").unwrap();
+        tokio::spawn(Arc::clone(&reloader).run(Arc::new(RwLock::new(gateway_config)), Arc::clone(&entries)));
+
+        tokio::time::sleep(std::time::Duration::from_secs(10 * 60 + 30)).await;
+
+        // Loaded, then reloaded every minute
+        assert_eq!(11, fetcher.fetches("users"));
+        assert_eq!(1, fetcher.fetches("hourly"));
+        // Retried after 1, 3 and 7 minutes as the backoff doubles from 60 seconds
+        assert_eq!(4, fetcher.fetches("failing"));
+        assert_eq!(4, entries.read().await[2].status.consecutive_failures);
+    }
+
+    #[tokio::test]
+    async fn unchanged_file_keeps_routes_and_recovers() {
+        let (fetcher, clock) = (FakeFetcher::default(), MockClock::new());
+        fetcher.set("users", Outcome::Succeed("v1"));
+        let reloader = Reloader::new(fetcher.clone(), clock.clone(), None);
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(reloader.load(&[config("users")], &settings()).await));
+        entries.write().await[0].openapi_file.as_mut().unwrap().contents = b"parsed once".to_vec();
+
+        fetcher.set("users", Outcome::Fail);
+        clock.advance(Duration::minutes(1));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+        fetcher.set("users", Outcome::Succeed("v1"));
+        clock.advance(Duration::minutes(1));
+        reloader.reload(&entries, &settings(), &[config("users")]).await;
+
+        let entries = entries.read().await;
+        assert_eq!(b"parsed once".to_vec(), entries[0].openapi_file.as_ref().unwrap().contents);
+        assert!(!entries[0].is_stale());
+        assert_eq!(Some(clock.now()), entries[0].status.last_success);
+    }
+
+    #[tokio::test]
+    async fn starts_from_cache_when_fetching_fails() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-reload-cache-{}", std::process::id()));
+        let (fetcher, clock) = (FakeFetcher::default(), MockClock::new());
+        fetcher.set("users", Outcome::Succeed("v1"));
+        fetcher.set("uncached", Outcome::Fail);
+        let cached_at = clock.now();
+        Reloader::new(fetcher.clone(), clock.clone(), Some(SpecCache::new(dir.clone())))
+            .load(&[config("users")], &settings())
+            .await;
+
+        // Restart while the upstream is down
+        fetcher.set("users", Outcome::Fail);
+        clock.advance(Duration::hours(1));
+        let reloader = Reloader::new(fetcher.clone(), clock.clone(), Some(SpecCache::new(dir.clone())));
+        let entries = reloader.load(&[config("users"), config("uncached")], &settings()).await;
+
+        assert!(entries[0].is_loaded());
+        assert!(entries[0].is_stale());
+        assert_eq!(1, entries[0].routes.len());
+        assert_eq!(Some(cached_at), entries[0].status.last_success);
+        assert!(!entries[1].is_loaded());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn conditional_fetch_with_etag_and_content_hash() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let full_responses = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&full_responses);
+        let upstream = Router::new()
+            .route("/etag.json", get(move |headers: HeaderMap| async move {
+                if headers.get(IF_NONE_MATCH).map(|etag| etag == "\"v1\"").unwrap_or(false) {
+                    return (StatusCode::NOT_MODIFIED, [(ETAG, "\"v1\"")], "");
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                (StatusCode::OK, [(ETAG, "\"v1\"")], SPEC)
+            }))
+            .route("/plain.json", get(|| async { SPEC }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(upstream.into_make_service()));
+        let fetcher = HttpFetcher::new(HttpClient::new());
+
+        for path in ["etag.json", "plain.json"] {
+            let config = OpenApiConfig { name: path.to_string(), url: format!("{base}/{path}"), ..Default::default() };
+
+            let version = match fetcher.fetch(&config, None).await.unwrap() {
+                Fetched::Modified(entry, _) => entry.openapi_file.unwrap().version(),
+                Fetched::NotModified(_) => panic!("first fetch of {path} must be modified"),
+            };
+            assert!(matches!(fetcher.fetch(&config, Some(&version)).await.unwrap(), Fetched::NotModified(_)));
+        }
+        assert_eq!(1, full_responses.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn refetch_referenced_files_of_unchanged_file() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let schemas = Arc::new(Mutex::new(r#"{"User": {"properties": {"id": {}}}}"#));
+        let served = Arc::clone(&schemas);
+        let upstream = Router::new()
+            .route("/openapi.json", get(|headers: HeaderMap| async move {
+                if headers.contains_key(IF_NONE_MATCH) {
+                    return (StatusCode::NOT_MODIFIED, [(ETAG, "\"v1\"")], "");
+                }
+                let spec = r#"{"openapi": "3.0.0", "paths": {"/users": {"get": {"responses": {"200": {"content": {
+                    "application/json": {"schema": {"$ref": "schemas.json#/User"}}
+                }}}}}}}"#;
+                (StatusCode::OK, [(ETAG, "\"v1\"")], spec)
+            }))
+            .route("/schemas.json", get(move || async move { *served.lock().unwrap() }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(upstream.into_make_service()));
+        let fetcher = HttpFetcher::new(HttpClient::new());
+        let config = OpenApiConfig { name: "users".to_string(), url: format!("{base}/openapi.json"), ..Default::default() };
+
+        let version = match fetcher.fetch(&config, None).await.unwrap() {
+            Fetched::Modified(entry, _) => entry.openapi_file.unwrap().version(),
+            Fetched::NotModified(_) => panic!("first fetch must be modified"),
+        };
+        assert_eq!(None, version.etag);
+        assert!(matches!(fetcher.fetch(&config, Some(&version)).await.unwrap(), Fetched::NotModified(_)));
+
+        *schemas.lock().unwrap() = r#"{"User": {"properties": {"id": {}, "name": {}}}}"#;
+        match fetcher.fetch(&config, Some(&version)).await.unwrap() {
+            Fetched::Modified(entry, _) => assert_ne!(version.content_hash, entry.openapi_file.unwrap().content_hash),
+            Fetched::NotModified(_) => panic!("a changed referenced file must be modified"),
+        }
+    }
+
+    #[test]
+    fn schedule_per_entry() {
+        let hourly = OpenApiConfig { reload_cron: Some("0 * * * *".to_string()), ..config("hourly") };
+        let never = OpenApiConfig { reload: false, ..config("never") };
+        let entries = vec![GatewayEntry::unloaded(config("global")), GatewayEntry::unloaded(hourly), GatewayEntry::unloaded(never)];
+        let at = |hour, minute, second| Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, second).unwrap();
+
+        let (due, next) = schedule(&entries, "* * * * *", at(0, 0, 30), at(0, 0, 45));
+        assert!(due.is_empty());
+        assert_eq!(Some(at(0, 1, 0)), next);
+
+        let (due, _) = schedule(&entries, "* * * * *", at(0, 0, 30), at(0, 1, 0));
+        assert_eq!(vec!["global"], due.iter().map(|config| config.name.as_str()).collect::<Vec<_>>());
+
+        let (due, _) = schedule(&entries, "* * * * *", at(0, 59, 30), at(1, 0, 0));
+        assert_eq!(vec!["global", "hourly"], due.iter().map(|config| config.name.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let settings = settings();
+
+        assert_eq!(Duration::seconds(60), backoff(1, &settings));
+        assert_eq!(Duration::seconds(240), backoff(3, &settings));
+        assert_eq!(Duration::seconds(3600), backoff(8, &settings));
+        assert_eq!(Duration::seconds(3600), backoff(u32::MAX, &settings));
+    }
+
+    const SPEC: &str = r#"{"openapi": "3.0.0", "paths": {"/users": {"get": {}}}}"#;
+
+    fn source() -> Source {
+        Source { content_type: ContentType::JSON, contents: SPEC.as_bytes().to_vec() }
+    }
+
+    fn settings() -> ReloadConfig {
+        ReloadConfig::default()
+    }
+
+    fn config(name: &str) -> OpenApiConfig {
+        OpenApiConfig {
+            name: name.to_string(),
+            url: format!("http://{name}/openapi.json"),
+            ..Default::default()
+        }
+    }
+
+    fn loaded(config: OpenApiConfig, content_hash: &str) -> GatewayEntry {
+        GatewayEntry {
+            config,
+            openapi_file: Some(OpenApiFile {
+                content_type: "application/json".to_string(),
+                contents: vec![],
+                content_hash: content_hash.to_string(),
+                etag: None,
+                last_modified: None,
+                base_paths: vec![String::new()],
+                path_base_paths: Default::default(),
+                diagnostics: vec![],
+            }),
+            routes: vec![],
+            status: EntryStatus::default(),
+            changes: Default::default(),
+        }
+    }
+}
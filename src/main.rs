@@ -1,26 +1,31 @@
 mod config;
 mod gateway;
 mod openapi;
+#[cfg(feature = "otlp")]
+mod telemetry;
 mod ui;
 mod web;
 
 use std::fmt::Debug;
+use crate::config::watch::spawn_config_watcher;
 use crate::config::{Config, OpenApiConfig};
-use crate::gateway::openapi::{ContentType, parse_openapi, ParseError};
-use crate::gateway::GatewayEntry;
+use crate::gateway::openapi::{content_hash, ContentType, parse_openapi, ParseError};
+use crate::gateway::bundle::{bundle, has_external_refs, BundleError};
+use crate::gateway::cache::{Source, SpecCache};
+use crate::gateway::reload::{Fetched, HttpFetcher, Reloader, SystemClock};
+use crate::gateway::{FileVersion, GatewayEntry};
 use crate::web::{simple_get, serve_with_config, HttpClient, HttpError};
-use chrono::Utc;
-use cron_parser::parse;
 use std::sync::Arc;
-use axum::http::{HeaderValue, Uri};
+use axum::http::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use tokio::sync::RwLock;
-use tokio::time::{sleep, Duration};
 use tracing::Level;
 use thiserror::Error;
 
 const CONFIG_FILE: &str = "openapi-gateway-config.toml";
 
 pub type RwGatewayEntries = Arc<RwLock<Vec<GatewayEntry>>>;
+pub type RwConfig = Arc<RwLock<Config>>;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -33,81 +38,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap();
 
 
-    let config = Config::parse_from_file(&format!("{}/{CONFIG_FILE}", std::env::current_dir().unwrap().to_str().unwrap()))
-        .unwrap_or_else(|_| Config::parse_from_env().unwrap());
-    let reload_cron = config.reload_cron.clone();
+    let config_path = std::env::current_dir().unwrap().join(CONFIG_FILE);
+    let (config, config_path) = match Config::parse_from_file(&config_path) {
+        Ok(config) => (config, Some(config_path)),
+        Err(_) => (Config::parse_from_env().unwrap(), None),
+    };
+    config.validate()?;
 
     let client = HttpClient::new();
 
-    let mut entries = vec![];
-    for config in config.openapi_urls.into_iter() {
-        entries.push(
-            fetch_entry(&client, &config)
-                .await
-                .unwrap_or(GatewayEntry {
-                    config: config.clone(),
-                    openapi_file: None,
-                    routes: vec![]
-                })
-        );
-    }
+    let reloader = Reloader::new(HttpFetcher::new(client.clone()), SystemClock, config.cache_dir.clone().map(SpecCache::new))
+        .with_reserved_paths(config.health.paths());
+    let reloader = Arc::new(reloader);
+    let entries = reloader.load(&config.entry_configs(), &config.reload).await;
 
     let entries = Arc::new(RwLock::from(entries));
-    spawn_reload_cron(reload_cron, Arc::clone(&entries)).await;
-
-    Ok(serve_with_config(client, entries, config.global_cors.clone()).await)
-}
-
-async fn spawn_reload_cron(reload_cron: String, entries: Arc<RwLock<Vec<GatewayEntry>>>) {
-    tokio::spawn(async move {
-        let client = HttpClient::new();
+    let config = Arc::new(RwLock::new(config));
+    tokio::spawn(Arc::clone(&reloader).run(Arc::clone(&config), Arc::clone(&entries)));
+    #[cfg(unix)]
+    tokio::spawn(Arc::clone(&reloader).reload_on_signal(Arc::clone(&config), Arc::clone(&entries)));
 
-        loop {
-            if let Ok(next) = parse(&reload_cron, &Utc::now()) {
-                let diff = next - Utc::now();
+    spawn_config_watcher(config_path.clone(), Arc::clone(&config), Arc::clone(&entries), Arc::clone(&reloader));
 
-                sleep(Duration::from_secs(diff.num_seconds() as u64)).await;
+    serve_with_config(client, entries, config, reloader, config_path).await?;
 
-                tracing::info!("Start collecting OpenAPI files");
+    Ok(())
+}
 
-                let mut reloaded_entries = {
-                    let mut entries = entries.read().await;
+async fn fetch_entry(client: &HttpClient, config: &OpenApiConfig) -> Result<GatewayEntry, FetchError> {
+    match fetch_entry_if_modified(client, config, None).await? {
+        Fetched::Modified(entry, _) => Ok(*entry),
+        Fetched::NotModified(_) => unreachable!("Not modified requires a previous version"),
+    }
+}
 
-                    let mut reload_entries = vec![];
-                    for entry in entries.iter() {
-                        reload_entries.push(
-                            fetch_entry(&client, &entry.config).await
-                        );
-                    }
+/// Sends the validators of the previous version, an unchanged file is neither returned nor parsed
+async fn fetch_entry_if_modified(client: &HttpClient, config: &OpenApiConfig, previous: Option<&FileVersion>) -> Result<Fetched, FetchError> {
+    let mut headers = HeaderMap::new();
+    if let Some(previous) = previous {
+        if let Some(etag) = previous.etag.as_ref().and_then(|etag| HeaderValue::from_str(etag).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = previous.last_modified.as_ref().and_then(|date| HeaderValue::from_str(date).ok()) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
 
-                    reload_entries
-                };
+    tracing::info!("Fetching: {}", &config.url);
+    let (status, response_headers, body) = match config.file_path() {
+        Some(path) => (StatusCode::OK, HeaderMap::new(), tokio::fs::read(path).await?.into()),
+        None => simple_get(client, &config.uri(), headers).await?,
+    };
 
-                {
-                    let mut entries = entries.write().await;
+    let header = |name| response_headers.get(name)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .map(|value| value.to_string());
 
-                    entries.iter_mut()
-                        .for_each(|entry| {
-                            if let Ok(reload_entry) = reloaded_entries.remove(0) {
-                                *entry = reload_entry;
-                            }
-                        })
-                }
+    if let (StatusCode::NOT_MODIFIED, Some(previous)) = (status, previous) {
+        tracing::info!("Not modified: {}", &config.url);
 
-                sleep(Duration::from_secs(1)).await;
-            }
-        }
-    });
-}
+        return Ok(Fetched::NotModified(FileVersion {
+            content_hash: previous.content_hash.clone(),
+            etag: header(ETAG).or_else(|| previous.etag.clone()),
+            last_modified: header(LAST_MODIFIED).or_else(|| previous.last_modified.clone()),
+        }));
+    }
+    if !status.is_success() {
+        return Err(HttpError::Status(status).into());
+    }
+    tracing::info!("Success fetching: {}", &config.url);
 
-async fn fetch_entry(client: &HttpClient, config: &OpenApiConfig) -> Result<GatewayEntry, FetchError> {
-    tracing::info!("Fetching: {}", &config.uri());
-    let response = simple_get(&client, &config.uri()).await?;
-    tracing::info!("Success fetching: {}", &config.uri());
-    let content_type = response.0.get("content-type")
-        .unwrap_or(&HeaderValue::from_str("").unwrap())
-        .to_str()
-        .unwrap()
+    let content_type = header(CONTENT_TYPE)
+        .unwrap_or_default()
         .to_lowercase();
 
     let content_type = match content_type.as_str() {
@@ -123,17 +125,39 @@ async fn fetch_entry(client: &HttpClient, config: &OpenApiConfig) -> Result<Gate
         }
     };
 
+    // Files referred to with `$ref` can change while this one does not. Without validators the next reload
+    // fetches them all, and the hash of the bundle tells whether any changed.
+    let (content_type, body, validators) = match bundle_external_refs(client, config, content_type, &body).await? {
+        Some(bundled) => (ContentType::JSON, bundled, false),
+        None => (content_type, body.to_vec(), true),
+    };
+
+    let version = FileVersion {
+        content_hash: content_hash(&body),
+        etag: header(ETAG).filter(|_| validators),
+        last_modified: header(LAST_MODIFIED).filter(|_| validators),
+    };
+    if previous.map(|previous| previous.content_hash == version.content_hash).unwrap_or(false) {
+        tracing::info!("Content unchanged: {}", &config.url);
+
+        return Ok(Fetched::NotModified(version));
+    }
+
     tracing::info!("Parse as: {:#?}", content_type);
 
     let parsed = parse_openapi(
         content_type,
         config.clone(),
-        &response.1,
+        &body,
     );
 
     match parsed {
-        Ok(parsed) => {
-            Ok(parsed)
+        Ok(mut parsed) => {
+            if let Some(file) = parsed.openapi_file.as_mut() {
+                file.set_version(version);
+            }
+
+            Ok(Fetched::Modified(Box::new(parsed), Source { content_type, contents: body }))
         }
         Err(err) => {
             tracing::error!("{:?}", err);
@@ -142,12 +166,39 @@ async fn fetch_entry(client: &HttpClient, config: &OpenApiConfig) -> Result<Gate
     }
 }
 
+/// Replaces `$ref`s to other files with their targets, the result is JSON. `None` for files without them.
+async fn bundle_external_refs(client: &HttpClient, config: &OpenApiConfig, content_type: ContentType, body: &[u8]) -> Result<Option<Vec<u8>>, FetchError> {
+    let document: serde_json::Value = match content_type {
+        ContentType::JSON => serde_json::from_slice(body).map_err(ParseError::from)?,
+        ContentType::YAML => serde_json::to_value(serde_yaml::from_slice::<serde_yaml::Value>(body).map_err(ParseError::from)?)
+            .map_err(ParseError::from)?,
+    };
+    if !has_external_refs(&document) {
+        return Ok(None);
+    }
+
+    let base = match config.file_path() {
+        // Relative paths are resolved against the working directory
+        Some(path) => format!("file://{}", std::path::absolute(&path)?.display()),
+        None => config.url.clone(),
+    };
+    let bundled = bundle(client, &base, document).await?;
+
+    Ok(Some(serde_json::to_vec(&bundled).map_err(ParseError::from)?))
+}
+
 #[derive(Error, Debug)]
 pub enum FetchError {
-    #[error("HTTP error")]
+    #[error("HTTP error: {0}")]
     HttpError(#[from] HttpError),
-    #[error("Unknown error")]
+    #[error("Unknown error: {0}")]
     UnknownError(#[from] Box<dyn std::error::Error + Send>),
-    #[error("Parse error")]
-    ParseError(#[from] ParseError)
+    #[error("Parse error: {0}")]
+    ParseError(#[from] ParseError),
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Bundle error: {0}")]
+    BundleError(#[from] BundleError),
+    #[error("Timeout after {0:?}")]
+    Timeout(std::time::Duration)
 }
\ No newline at end of file
@@ -1,37 +1,246 @@
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use std::collections::BTreeMap;
 
+/// Keys of a path item that are operations
+pub const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
 type PathName = String;
 type HttpMethod = String;
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct OpenApiV3 {
+    /// Optional since OpenAPI 3.1, webhooks are not routed
     #[serde(default)]
-    pub servers: Vec<Server>,
     pub paths: BTreeMap<PathName, Path>,
+    #[serde(default)]
+    pub components: Components,
+    /// Swagger 2 keeps reusable parameters at the top level
+    #[serde(default)]
+    pub parameters: BTreeMap<String, ParameterOrRef>,
+    /// Swagger 2 keeps reusable schemas at the top level
+    #[serde(default, deserialize_with = "lenient_map")]
+    pub definitions: BTreeMap<String, Value>,
 }
 
-#[derive(Clone, Deserialize, Debug)]
-pub struct Server {
-    pub url: String,
+#[derive(Clone, Deserialize, Debug, Default)]
+pub struct Components {
+    #[serde(default)]
+    pub parameters: BTreeMap<String, ParameterOrRef>,
+    #[serde(default, deserialize_with = "lenient_map")]
+    pub schemas: BTreeMap<String, Value>,
+    #[serde(default, deserialize_with = "lenient_map")]
+    pub responses: BTreeMap<String, Response>,
+    #[serde(default, rename = "requestBodies", deserialize_with = "lenient_map")]
+    pub request_bodies: BTreeMap<String, RequestBody>,
 }
 
+/// Path item, keys other than `parameters` and the operations are ignored
 #[derive(Clone, Deserialize, Debug)]
+#[serde(try_from = "BTreeMap<String, serde_json::Value>")]
 pub struct Path {
-    #[serde(default)]
-    pub parameters: Vec<Parameter>,
-    #[serde(flatten)]
+    pub parameters: Vec<ParameterOrRef>,
     pub methods: BTreeMap<HttpMethod, PathMethod>
 }
 
 #[derive(Clone, Deserialize, Debug)]
 pub struct PathMethod {
-    pub parameters: Option<Vec<Parameter>>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterOrRef>,
+    #[serde(rename = "operationId")]
+    pub operation_id: Option<String>,
+    #[serde(default, rename = "requestBody", deserialize_with = "lenient")]
+    pub request_body: Option<RequestBody>,
+    /// By status code, extensions are left out
+    #[serde(default, deserialize_with = "responses")]
+    pub responses: BTreeMap<String, Response>,
+    #[serde(default)]
+    pub deprecated: bool,
+    /// Date after which a deprecated operation is removed
+    #[serde(default, rename = "x-sunset")]
+    pub sunset: Option<serde_json::Value>,
 }
 
 #[derive(Clone, Deserialize, Debug)]
+pub struct RequestBody {
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub content: BTreeMap<String, MediaType>,
+    #[serde(rename = "$ref")]
+    pub reference: Option<String>,
+}
+
+/// Response, only its schemas are of interest
+#[derive(Clone, Deserialize, Debug)]
+pub struct Response {
+    #[serde(default)]
+    pub content: BTreeMap<String, MediaType>,
+    /// Swagger 2 has a single schema per response
+    pub schema: Option<Value>,
+    #[serde(rename = "$ref")]
+    pub reference: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct MediaType {
+    pub schema: Option<Value>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Parameter {
     pub name: String,
     #[serde(rename = "in")]
     pub in_type: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ParameterOrRef {
+    Ref {
+        #[serde(rename = "$ref")]
+        reference: String,
+    },
+    Parameter(Parameter),
+}
+
+impl TryFrom<BTreeMap<String, serde_json::Value>> for Path {
+    type Error = serde_json::Error;
+
+    fn try_from(mut item: BTreeMap<String, serde_json::Value>) -> Result<Path, Self::Error> {
+        let parameters = match item.remove("parameters") {
+            Some(parameters) => serde_json::from_value(parameters)?,
+            None => vec![],
+        };
+        let methods = item.into_iter()
+            .filter(|(key, _)| METHODS.contains(&key.as_str()))
+            .map(|(key, method)| Ok((key, serde_json::from_value(method)?)))
+            .collect::<Result<_, Self::Error>>()?;
+
+        Ok(Path { parameters, methods })
+    }
+}
+
+impl OpenApiV3 {
+    /// Parameters of an operation: those of the path merged with those of the operation by name and location,
+    /// the operation overriding the path. `$ref`s to reusable parameters are resolved.
+    pub fn parameters(&self, path: &Path, method: &PathMethod) -> Vec<Parameter> {
+        let mut parameters: Vec<Parameter> = vec![];
+
+        for parameter in path.parameters.iter().chain(&method.parameters).filter_map(|parameter| self.resolve(parameter)) {
+            match parameters.iter_mut().find(|other| other.name == parameter.name && other.in_type == parameter.in_type) {
+                Some(other) => *other = parameter,
+                None => parameters.push(parameter),
+            }
+        }
+
+        parameters
+    }
+
+    /// Request body of an operation, with a `$ref` to a reusable request body resolved
+    pub fn request_body<'a>(&'a self, method: &'a PathMethod) -> Option<&'a RequestBody> {
+        let body = method.request_body.as_ref()?;
+        match &body.reference {
+            Some(reference) => reference.strip_prefix("#/components/requestBodies/")
+                .and_then(|name| self.components.request_bodies.get(&unescape(name))),
+            None => Some(body),
+        }
+    }
+
+    /// Response with a `$ref` to a reusable response resolved
+    pub fn response<'a>(&'a self, response: &'a Response) -> Option<&'a Response> {
+        match &response.reference {
+            Some(reference) => reference.strip_prefix("#/components/responses/")
+                .and_then(|name| self.components.responses.get(&unescape(name))),
+            None => Some(response),
+        }
+    }
+
+    /// Reusable schema a `$ref` points to
+    pub fn schema(&self, reference: &str) -> Option<&Value> {
+        reference.strip_prefix("#/components/schemas/")
+            .and_then(|name| self.components.schemas.get(&unescape(name)))
+            .or_else(|| reference.strip_prefix("#/definitions/").and_then(|name| self.definitions.get(&unescape(name))))
+    }
+
+    fn resolve(&self, parameter: &ParameterOrRef) -> Option<Parameter> {
+        let mut parameter = parameter;
+        // Reusable parameters may refer to each other, but not endlessly
+        for _ in 0..8 {
+            let reference = match parameter {
+                ParameterOrRef::Parameter(parameter) => return Some(parameter.clone()),
+                ParameterOrRef::Ref { reference } => reference,
+            };
+
+            let resolved = reference.strip_prefix("#/components/parameters/")
+                .and_then(|name| self.components.parameters.get(&unescape(name)))
+                .or_else(|| reference.strip_prefix("#/parameters/").and_then(|name| self.parameters.get(&unescape(name))));
+            match resolved {
+                Some(resolved) => parameter = resolved,
+                None => {
+                    tracing::warn!("Parameter {reference} not found");
+                    return None;
+                }
+            }
+        }
+
+        tracing::warn!("Parameter $refs nested too deep");
+        None
+    }
+}
+
+impl RequestBody {
+    /// Schemas by media type
+    pub fn schemas(&self) -> BTreeMap<&str, &Value> {
+        schemas(&self.content)
+    }
+}
+
+impl Response {
+    /// Schemas by media type, a Swagger 2 schema has an empty media type
+    pub fn schemas(&self) -> BTreeMap<&str, &Value> {
+        let mut schemas = schemas(&self.content);
+        if let Some(schema) = &self.schema {
+            schemas.insert("", schema);
+        }
+
+        schemas
+    }
+}
+
+fn schemas(content: &BTreeMap<String, MediaType>) -> BTreeMap<&str, &Value> {
+    content.iter()
+        .filter_map(|(media_type, content)| content.schema.as_ref().map(|schema| (media_type.as_str(), schema)))
+        .collect()
+}
+
+/// Request bodies, responses and schemas are only read to diff versions, so routing never fails on them.
+/// What does not deserialize is left out.
+fn lenient<'de, D: Deserializer<'de>, T: DeserializeOwned>(deserializer: D) -> Result<Option<T>, D::Error> {
+    Ok(T::deserialize(&Value::deserialize(deserializer)?).ok())
+}
+
+fn lenient_map<'de, D: Deserializer<'de>, T: DeserializeOwned>(deserializer: D) -> Result<BTreeMap<String, T>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+
+    Ok(value.as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| T::deserialize(value).ok().map(|value| (key.clone(), value)))
+        .collect())
+}
+
+fn responses<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Response>, D::Error> {
+    let mut responses: BTreeMap<String, Response> = lenient_map(deserializer)?;
+    responses.retain(|status, _| !status.starts_with("x-"));
+
+    Ok(responses)
+}
+
+/// Name from a segment of a JSON pointer
+fn unescape(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
 }
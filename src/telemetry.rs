@@ -0,0 +1,581 @@
+use crate::config::TelemetryConfig;
+use crate::web::{HttpClient, MatchedRoute};
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderValue, Request, Uri};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, Duration};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+const MAX_BATCH_SIZE: usize = 512;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// W3C trace context as carried by the `traceparent` and `tracestate` headers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub flags: u8,
+    pub trace_state: Option<String>,
+}
+
+impl TraceContext {
+    pub fn extract(headers: &HeaderMap) -> Option<TraceContext> {
+        let traceparent = headers.get(TRACEPARENT)?.to_str().ok()?;
+        let mut context = Self::parse_traceparent(traceparent)?;
+
+        context.trace_state = headers.get(TRACESTATE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+
+        Some(context)
+    }
+
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        headers.insert(TRACEPARENT, HeaderValue::from_str(&self.traceparent()).unwrap());
+
+        match self.trace_state.as_ref().and_then(|state| HeaderValue::from_str(state).ok()) {
+            Some(state) => { headers.insert(TRACESTATE, state); }
+            None => { headers.remove(TRACESTATE); }
+        }
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", hex(&self.trace_id), hex(&self.span_id), self.flags)
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 0x01 == 0x01
+    }
+
+    fn parse_traceparent(value: &str) -> Option<TraceContext> {
+        let parts: Vec<&str> = value.trim().split('-').collect();
+        if parts.len() < 4 {
+            return None;
+        }
+
+        let version = u8::from_str_radix(parts[0], 16).ok().filter(|_| parts[0].len() == 2)?;
+        // Version 00 has exactly four fields, future versions may append more
+        if version == 0xff || (version == 0 && parts.len() != 4) {
+            return None;
+        }
+
+        let trace_id: [u8; 16] = unhex(parts[1])?.try_into().ok()?;
+        let span_id: [u8; 8] = unhex(parts[2])?.try_into().ok()?;
+        let flags = u8::from_str_radix(parts[3], 16).ok().filter(|_| parts[3].len() == 2)?;
+
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+
+        Some(TraceContext { trace_id, span_id, flags, trace_state: None })
+    }
+
+    fn child(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: new_span_id(),
+            flags: self.flags,
+            trace_state: self.trace_state.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SpanKind {
+    Server,
+    Client,
+}
+
+/// Handle to the exporter task, cheap to clone.
+#[derive(Clone)]
+pub struct Tracer {
+    sender: mpsc::UnboundedSender<ExportMessage>,
+}
+
+enum ExportMessage {
+    Span(SpanData),
+    #[cfg_attr(not(test), allow(dead_code))]
+    Flush(oneshot::Sender<()>),
+}
+
+impl Tracer {
+    pub fn new(config: &TelemetryConfig, client: HttpClient) -> Tracer {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let endpoint = format!("{}/v1/traces", config.otlp_endpoint.trim_end_matches('/'));
+
+        tracing::info!("Exporting traces to: {endpoint}");
+        tokio::spawn(export_spans(
+            client,
+            Uri::try_from(endpoint).expect("otlp_endpoint is validated with the config"),
+            Arc::new(config.service_name.clone()),
+            receiver,
+        ));
+
+        Tracer { sender }
+    }
+
+    pub fn start_span(&self, kind: SpanKind, name: &str, parent: Option<&TraceContext>) -> ActiveSpan {
+        let context = match parent {
+            Some(parent) => parent.child(),
+            None => TraceContext {
+                trace_id: new_trace_id(),
+                span_id: new_span_id(),
+                flags: 0x01,
+                trace_state: None,
+            },
+        };
+
+        ActiveSpan {
+            tracer: self.clone(),
+            parent_span_id: parent.map(|parent| parent.span_id),
+            context,
+            kind,
+            name: name.to_string(),
+            start: now_unix_nanos(),
+            attributes: vec![],
+            error: false,
+        }
+    }
+
+    /// Export all spans that are queued so far.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+
+        if self.sender.send(ExportMessage::Flush(sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+}
+
+pub struct ActiveSpan {
+    tracer: Tracer,
+    parent_span_id: Option<[u8; 8]>,
+    context: TraceContext,
+    kind: SpanKind,
+    name: String,
+    start: u128,
+    attributes: Vec<(&'static str, AttributeValue)>,
+    error: bool,
+}
+
+impl ActiveSpan {
+    pub fn context(&self) -> &TraceContext {
+        &self.context
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn set_attribute<V: Into<AttributeValue>>(&mut self, key: &'static str, value: V) {
+        self.attributes.push((key, value.into()));
+    }
+
+    pub fn set_error(&mut self) {
+        self.error = true;
+    }
+
+    pub fn end(self) {
+        if !self.context.is_sampled() {
+            return;
+        }
+
+        let span = SpanData {
+            trace_id: hex(&self.context.trace_id),
+            span_id: hex(&self.context.span_id),
+            parent_span_id: self.parent_span_id.map(|id| hex(&id)).unwrap_or_default(),
+            trace_state: self.context.trace_state.clone().unwrap_or_default(),
+            name: self.name,
+            kind: match self.kind {
+                SpanKind::Server => 2,
+                SpanKind::Client => 3,
+            },
+            start_time_unix_nano: self.start.to_string(),
+            end_time_unix_nano: now_unix_nanos().to_string(),
+            attributes: self.attributes
+                .into_iter()
+                .map(|(key, value)| KeyValue { key: key.to_string(), value })
+                .collect(),
+            status: SpanStatus { code: if self.error { 2 } else { 1 } },
+        };
+
+        let _ = self.tracer.sender.send(ExportMessage::Span(span));
+    }
+}
+
+/// Per request handle placed in the request extensions by [`trace_requests`], so the
+/// gateway handler can start a client span for the upstream call.
+#[derive(Clone)]
+pub struct RequestTrace {
+    tracer: Tracer,
+    context: TraceContext,
+}
+
+impl RequestTrace {
+    /// Starts a client span and injects its context into the outgoing headers.
+    pub fn start_client_span(&self, name: &str, uri: &Uri, headers: &mut HeaderMap) -> ActiveSpan {
+        let mut span = self.tracer.start_span(SpanKind::Client, name, Some(&self.context));
+        span.context().inject(headers);
+        span.set_attribute("http.url", uri.to_string());
+        if let Some(host) = uri.host() {
+            span.set_attribute("server.address", host.to_string());
+        }
+
+        span
+    }
+}
+
+/// Middleware creating a server span for every request passing through the gateway.
+pub async fn trace_requests(
+    State(tracer): State<Tracer>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let method = req.method().to_string();
+    let parent = TraceContext::extract(req.headers());
+
+    let mut span = tracer.start_span(SpanKind::Server, &method, parent.as_ref());
+    span.set_attribute("http.method", method.clone());
+    span.set_attribute("http.target", req.uri().path().to_string());
+
+    req.extensions_mut().insert(RequestTrace {
+        tracer: tracer.clone(),
+        context: span.context().clone(),
+    });
+
+    let response = next.run(req).await;
+
+    if let Some(matched) = response.extensions().get::<MatchedRoute>() {
+        span.set_name(matched.operation_id.as_deref().unwrap_or(&method));
+        span.set_attribute("gateway.entry", matched.entry.clone());
+    }
+    span.set_attribute("http.status_code", response.status().as_u16() as i64);
+    if response.status().is_server_error() {
+        span.set_error();
+    }
+    span.end();
+
+    response
+}
+
+async fn export_spans(
+    client: HttpClient,
+    endpoint: Uri,
+    service_name: Arc<String>,
+    mut receiver: mpsc::UnboundedReceiver<ExportMessage>,
+) {
+    let mut batch = vec![];
+    let mut ticker = interval(EXPORT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => {
+                match message {
+                    Some(ExportMessage::Span(span)) => {
+                        batch.push(span);
+
+                        if batch.len() >= MAX_BATCH_SIZE {
+                            export_batch(&client, &endpoint, &service_name, &mut batch).await;
+                        }
+                    }
+                    Some(ExportMessage::Flush(done)) => {
+                        export_batch(&client, &endpoint, &service_name, &mut batch).await;
+                        let _ = done.send(());
+                    }
+                    None => {
+                        export_batch(&client, &endpoint, &service_name, &mut batch).await;
+                        return;
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                export_batch(&client, &endpoint, &service_name, &mut batch).await;
+            }
+        }
+    }
+}
+
+async fn export_batch(client: &HttpClient, endpoint: &Uri, service_name: &str, batch: &mut Vec<SpanData>) {
+    if batch.is_empty() {
+        return;
+    }
+
+    let request = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Resource {
+                attributes: vec![KeyValue {
+                    key: "service.name".to_string(),
+                    value: service_name.to_string().into(),
+                }],
+            },
+            scope_spans: vec![ScopeSpans {
+                scope: Scope {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                },
+                spans: std::mem::take(batch),
+            }],
+        }],
+    };
+
+    let request = Request::post(endpoint)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&request).unwrap()))
+        .unwrap();
+
+    match client.request(request).await {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => tracing::warn!("OTLP export rejected with status: {}", response.status()),
+        Err(err) => tracing::warn!("OTLP export failed: {err}"),
+    }
+}
+
+// OTLP/HTTP JSON encoding, see https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportTraceServiceRequest {
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    resource: Resource,
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Serialize)]
+struct ScopeSpans {
+    scope: Scope,
+    spans: Vec<SpanData>,
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    parent_span_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    trace_state: String,
+    name: String,
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<KeyValue>,
+    status: SpanStatus,
+}
+
+#[derive(Serialize, Debug)]
+struct SpanStatus {
+    code: u8,
+}
+
+#[derive(Serialize, Debug)]
+struct KeyValue {
+    key: String,
+    value: AttributeValue,
+}
+
+#[derive(Serialize, Debug)]
+pub enum AttributeValue {
+    #[serde(rename = "stringValue")]
+    String(String),
+    // int64 values are encoded as strings in OTLP JSON
+    #[serde(rename = "intValue", serialize_with = "serialize_int")]
+    Int(i64),
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(value: i64) -> Self {
+        AttributeValue::Int(value)
+    }
+}
+
+fn serialize_int<S: serde::Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+fn new_trace_id() -> [u8; 16] {
+    loop {
+        let id: [u8; 16] = rand::random();
+        if id != [0; 16] {
+            return id;
+        }
+    }
+}
+
+fn new_span_id() -> [u8; 8] {
+    loop {
+        let id: [u8; 8] = rand::random();
+        if id != [0; 8] {
+            return id;
+        }
+    }
+}
+
+fn now_unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.chars().all(|char| char.is_ascii_hexdigit() && !char.is_ascii_uppercase()) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::TelemetryConfig;
+    use crate::telemetry::{SpanKind, TraceContext, Tracer};
+    use crate::web::HttpClient;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, HeaderValue};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::Value;
+    use std::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn extract_traceparent_and_tracestate() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static(TRACEPARENT));
+        headers.insert("tracestate", HeaderValue::from_static("vendor=value"));
+
+        let context = TraceContext::extract(&headers).unwrap();
+
+        assert_eq!(TRACEPARENT, context.traceparent());
+        assert_eq!(Some("vendor=value".to_string()), context.trace_state);
+        assert!(context.is_sampled());
+    }
+
+    #[test]
+    fn reject_invalid_traceparent() {
+        let invalid = [
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6-00f067aa0ba902b7-01",
+            "not a traceparent",
+        ];
+
+        for value in invalid {
+            let mut headers = HeaderMap::new();
+            headers.insert("traceparent", HeaderValue::from_static(value));
+
+            assert_eq!(None, TraceContext::extract(&headers), "{value}");
+        }
+    }
+
+    #[test]
+    fn inject_child_context() {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static(TRACEPARENT));
+        let parent = TraceContext::extract(&headers).unwrap();
+
+        let child = parent.child();
+        let mut outgoing = HeaderMap::new();
+        child.inject(&mut outgoing);
+
+        let injected = TraceContext::extract(&outgoing).unwrap();
+        assert_eq!(parent.trace_id, injected.trace_id);
+        assert_ne!(parent.span_id, injected.span_id);
+    }
+
+    #[tokio::test]
+    async fn export_spans_to_collector() {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Bytes>();
+        let collector = Router::new()
+            .route("/v1/traces", post(|State(sender): State<mpsc::UnboundedSender<Bytes>>, body: Bytes| async move {
+                sender.send(body).unwrap();
+            }))
+            .with_state(sender);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(collector.into_make_service()));
+
+        let tracer = Tracer::new(
+            &TelemetryConfig { otlp_endpoint: endpoint, service_name: "test-gateway".to_string() },
+            HttpClient::new(),
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", HeaderValue::from_static(TRACEPARENT));
+        let incoming = TraceContext::extract(&headers).unwrap();
+
+        let server = tracer.start_span(SpanKind::Server, "getPetById", Some(&incoming));
+        let client = tracer.start_span(SpanKind::Client, "getPetById", Some(server.context()));
+        let mut outgoing = HeaderMap::new();
+        client.context().inject(&mut outgoing);
+        let client_span_id = client.context().span_id;
+        client.end();
+        server.end();
+        tracer.flush().await;
+
+        let body: Value = serde_json::from_slice(&receiver.recv().await.unwrap()).unwrap();
+        let resource_spans = &body["resourceSpans"][0];
+        assert_eq!("test-gateway", resource_spans["resource"]["attributes"][0]["value"]["stringValue"]);
+
+        let spans = resource_spans["scopeSpans"][0]["spans"].as_array().unwrap();
+        assert_eq!(2, spans.len());
+        let (client, server) = (&spans[0], &spans[1]);
+
+        assert_eq!("getPetById", client["name"]);
+        assert_eq!(3, client["kind"]);
+        assert_eq!(2, server["kind"]);
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", server["traceId"]);
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", client["traceId"]);
+        assert_eq!("00f067aa0ba902b7", server["parentSpanId"]);
+        assert_eq!(server["spanId"], client["parentSpanId"]);
+
+        let injected = TraceContext::extract(&outgoing).unwrap();
+        assert_eq!(client_span_id, injected.span_id);
+    }
+}
@@ -1,25 +1,30 @@
+mod access_log;
+mod admin;
+mod assets;
+mod deprecation;
 mod handler;
+mod health;
 
-use std::convert::Infallible;
-use std::error::Error;
-use crate::web::handler::{gateway_handler, swagger_conf_handler, swagger_def_handler};
-use crate::RwGatewayEntries;
-use axum::body::{Body, Bytes, StreamBody};
+pub use handler::MatchedRoute;
+
+use crate::gateway::reload::HttpReloader;
+use crate::web::deprecation::DeprecationUsage;
+use crate::web::handler::{gateway_handler, merged_openapi_json_handler, merged_openapi_yaml_handler, redoc_handler, swagger_conf_handler, swagger_def_handler};
+use crate::web::health::{liveness_handler, readiness_handler};
+use crate::{RwConfig, RwGatewayEntries};
+use axum::body::{Body, Bytes};
 use axum::http::{HeaderMap, Request, StatusCode, Uri};
-use axum::routing::{any, get, get_service};
+use axum::routing::get;
+use axum::middleware::from_fn_with_state;
 use axum::Router;
+use std::sync::Arc;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 use std::net::SocketAddr;
-use axum::response::{IntoResponse, Response};
-use tokio::io::AsyncReadExt;
-use tokio::time::{Duration, sleep};
-use std::sync::Arc;
-use axum::http::uri::Scheme;
+use std::path::PathBuf;
+use axum::response::Response;
 use axum_macros::FromRef;
-use tokio::io;
-use tower_http::services::{ServeDir, ServeFile};
-use crate::config::CorsConfig;
+use tower_http::compression::CompressionLayer;
 
 type HyperHttpsClient = hyper::client::Client<HttpsConnector<HttpConnector>, Body>;
 type HyperHttpClient = hyper::client::Client<HttpConnector, Body>;
@@ -65,33 +70,37 @@ fn new_hyper_https_client() -> HyperHttpsClient {
     hyper::Client::builder().build(https)
 }
 
-pub async fn simple_get(client: &HttpClient, uri: &Uri) -> Result<(HeaderMap, Bytes), HttpError> {
+pub async fn simple_get(client: &HttpClient, uri: &Uri, headers: HeaderMap) -> Result<(StatusCode, HeaderMap, Bytes), HttpError> {
     let scheme = uri.scheme_str()
         .unwrap_or("http");
 
+    let mut request = Request::get(uri).body(Body::empty()).unwrap();
+    *request.headers_mut() = headers;
+
     let response = match scheme {
         "http" => {
             client
                 .http_client
-                .request(Request::get(uri).body(Body::empty()).unwrap())
+                .request(request)
                 .await
         }
         "https" => {
             client
                 .https_client
-                .request(Request::get(uri).body(Body::empty()).unwrap())
+                .request(request)
                 .await
         }
-        _ => panic!("Unknown")
+        _ => return Err(HttpError::UnsupportedScheme(scheme.to_string()))
     };
 
     match response {
         Ok(response) => {
+            let status = response.status();
             let headers = response.headers().clone();
 
-            let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let bytes = hyper::body::to_bytes(response.into_body()).await?;
 
-            Ok((headers, bytes))
+            Ok((status, headers, bytes))
         }
         Err(err) => { Err(HttpError::Error(err)) }
     }
@@ -99,40 +108,140 @@ pub async fn simple_get(client: &HttpClient, uri: &Uri) -> Result<(HeaderMap, By
 
 #[derive(thiserror::Error, Debug)]
 pub enum HttpError {
-    #[error("Some error")]
-    Error(#[from] hyper::Error)
+    #[error("{0}")]
+    Error(#[from] hyper::Error),
+    #[error("unexpected status {0}")]
+    Status(StatusCode),
+    #[error("unsupported scheme {0}")]
+    UnsupportedScheme(String)
 }
 
 #[derive(Clone, FromRef)]
 struct AppState {
     client: HttpClient,
     entries: RwGatewayEntries,
-    global_cors_config: Option<CorsConfig>
+    config: RwConfig,
+    usage: Arc<DeprecationUsage>,
 }
 pub async fn serve_with_config(
     client: HttpClient,
     entries: RwGatewayEntries,
-    global_cors_config: Option<CorsConfig>
-) {
-    let serve_dir = ServeDir::new("redoc").not_found_service(ServeFile::new("redoc/index.html"));
-    let serve_dir = get_service(serve_dir).handle_error(handle_error);
+    shared_config: RwConfig,
+    reloader: Arc<HttpReloader>,
+    config_path: Option<PathBuf>
+) -> Result<(), hyper::Error> {
+    // Snapshot of the settings that require a restart to change
+    let config = shared_config.read().await.clone();
+
+    let usage = Arc::new(DeprecationUsage::default());
 
     let app = Router::new()
+        .route(&config.health.liveness_path, get(liveness_handler))
+        .route(&config.health.readiness_path, get(readiness_handler))
         .route("/docs/swagger-config.json", get(swagger_conf_handler))
-        .route("/docs/defs/:def", get(swagger_def_handler))
-        .nest_service("/redoc/", serve_dir)
+        .route("/docs/defs/*def", get(swagger_def_handler).layer(CompressionLayer::new()))
+        .route("/docs/openapi.json", get(merged_openapi_json_handler))
+        .route("/docs/openapi.yaml", get(merged_openapi_yaml_handler))
+        .route("/redoc/", get(redoc_handler))
+        .route("/redoc/*file", get(redoc_handler))
         .fallback(gateway_handler)
-        .with_state(AppState { client, entries, global_cors_config })
+        .with_state(AppState {
+            client: client.clone(),
+            entries: Arc::clone(&entries),
+            config: Arc::clone(&shared_config),
+            usage: Arc::clone(&usage),
+        })
         ;
 
+    #[cfg(feature = "otlp")]
+    let app = match &config.telemetry {
+        Some(telemetry_config) => app.layer(from_fn_with_state(
+            crate::telemetry::Tracer::new(telemetry_config, client.clone()),
+            crate::telemetry::trace_requests,
+        )),
+        None => app
+    };
+
+    #[cfg(not(feature = "otlp"))]
+    if config.telemetry.is_some() {
+        tracing::warn!("Telemetry is configured, but the gateway is built without the `otlp` feature");
+    }
+
+    let app = match &config.admin {
+        Some(admin_config) => {
+            let admin = admin::admin_router(
+                Arc::clone(&entries),
+                client.clone(),
+                reloader,
+                Arc::clone(&shared_config),
+                usage,
+                admin_config,
+                config_path,
+            );
+
+            match admin_config.port {
+                Some(port) => {
+                    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+                    // Bound before spawning, so a port in use stops the gateway instead of only the admin API
+                    let server = axum::Server::try_bind(&addr)?.serve(admin.into_make_service());
+                    tracing::info!("admin API listening on {}", addr);
+                    tokio::spawn(async move {
+                        if let Err(err) = server.await {
+                            tracing::error!("admin API stopped: {err}");
+                        }
+                    });
+
+                    app
+                }
+                None => {
+                    tracing::info!("admin API available under {}", admin_config.path);
+                    app.nest(&admin_config.path, admin)
+                }
+            }
+        }
+        None => app
+    };
+
+    let app = if config.access_log.enabled {
+        app.layer(from_fn_with_state(Arc::new(config.access_log.clone()), access_log::log_requests))
+    } else {
+        app
+    };
+
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
     tracing::info!("gateway proxy listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+    axum::Server::try_bind(&addr)?
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
-        .unwrap();
 }
 
-async fn handle_error(_err: io::Error) -> impl IntoResponse {
-    (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong...")
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::gateway::reload::{HttpFetcher, Reloader, SystemClock};
+    use crate::web::{serve_with_config, HttpClient};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn fail_when_admin_port_is_in_use() {
+        let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config: Config = toml::from_str(&format!(
+            "reload_cron = \"* * * * *\"\nopenapi_urls = []\n\n[admin]\ntoken = \"secret\"\nport = {port}\n"
+        )).unwrap();
+
+        let serve = serve_with_config(
+            HttpClient::new(),
+            Arc::default(),
+            Arc::new(RwLock::new(config)),
+            Arc::new(Reloader::new(HttpFetcher::new(HttpClient::new()), SystemClock, None)),
+            None,
+        );
+
+        let result = tokio::time::timeout(Duration::from_secs(5), serve).await.expect("serving must fail right away");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use crate::config::{AccessLogConfig, AccessLogFormat};
+use crate::web::handler::UpstreamResponse;
+use crate::web::MatchedRoute;
+use axum::body::{boxed, Body, BoxBody, Bytes, HttpBody};
+use axum::extract::{ConnectInfo, State};
+use axum::http::header::{REFERER, USER_AGENT};
+use axum::http::{HeaderMap, Request, Uri};
+use http_body::SizeHint;
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Serialize, Debug)]
+struct AccessLogRecord {
+    timestamp: DateTime<Utc>,
+    client_ip: Option<String>,
+    method: String,
+    path: String,
+    protocol: String,
+    status: u16,
+    /// Of the response body as sent, also when streamed without `Content-Length`
+    bytes: u64,
+    entry: Option<String>,
+    operation_id: Option<String>,
+    upstream_status: Option<u16>,
+    duration_ms: f64,
+    upstream_duration_ms: Option<f64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    headers: BTreeMap<String, String>,
+}
+
+/// Middleware logging one line per request with target `access_log`, once the response body is sent.
+pub async fn log_requests(
+    State(config): State<Arc<AccessLogConfig>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let start = Instant::now();
+    let timestamp = Utc::now();
+
+    let client_ip = req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let method = req.method().to_string();
+    let path = redact_query(req.uri(), &config.redact_query_params);
+    let protocol = format!("{:?}", req.version());
+    let referer = header(req.headers(), REFERER.as_str());
+    let user_agent = header(req.headers(), USER_AGENT.as_str());
+    let headers = logged_headers(req.headers(), &config.log_headers, &config.redact_headers);
+
+    let response = next.run(req).await;
+
+    let matched = response.extensions().get::<MatchedRoute>();
+    let upstream = response.extensions().get::<UpstreamResponse>();
+
+    let record = AccessLogRecord {
+        timestamp,
+        client_ip,
+        method,
+        path,
+        protocol,
+        status: response.status().as_u16(),
+        bytes: 0,
+        entry: matched.map(|matched| matched.entry.clone()),
+        operation_id: matched.and_then(|matched| matched.operation_id.clone()),
+        upstream_status: upstream.map(|upstream| upstream.status.as_u16()),
+        duration_ms: 0.0,
+        upstream_duration_ms: upstream.map(|upstream| upstream.duration.as_secs_f64() * 1000.0),
+        referer,
+        user_agent,
+        headers,
+    };
+
+    let log = PendingLog { record, start, format: config.format.clone() };
+    response.map(|body| boxed(LoggedBody { inner: body, log: Some(log) }))
+}
+
+struct PendingLog {
+    record: AccessLogRecord,
+    start: Instant,
+    format: AccessLogFormat,
+}
+
+/// Response body that logs the request when it ends or is dropped, so the duration includes sending the body
+struct LoggedBody {
+    inner: BoxBody,
+    log: Option<PendingLog>,
+}
+
+impl LoggedBody {
+    fn finish(&mut self) {
+        if let Some(mut log) = self.log.take() {
+            log.record.duration_ms = log.start.elapsed().as_secs_f64() * 1000.0;
+            tracing::info!(target: "access_log", "{}", format_record(&log.record, &log.format));
+        }
+    }
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_data(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(data))) => {
+                if let Some(log) = self.log.as_mut() {
+                    log.record.bytes += data.len() as u64;
+                }
+            }
+            Poll::Ready(None) => self.finish(),
+            _ => {}
+        }
+
+        poll
+    }
+
+    fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+fn format_record(record: &AccessLogRecord, format: &AccessLogFormat) -> String {
+    match format {
+        AccessLogFormat::Json => serde_json::to_string(record).unwrap(),
+        AccessLogFormat::Common => format!("{}{}", common_log_line(record), gateway_fields(record)),
+        AccessLogFormat::Combined => format!(
+            "{} \"{}\" \"{}\"{}",
+            common_log_line(record),
+            record.referer.as_deref().unwrap_or("-"),
+            record.user_agent.as_deref().unwrap_or("-"),
+            gateway_fields(record)
+        ),
+    }
+}
+
+fn common_log_line(record: &AccessLogRecord) -> String {
+    format!(
+        "{} - - [{}] \"{} {} {}\" {} {}",
+        record.client_ip.as_deref().unwrap_or("-"),
+        record.timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+        record.method,
+        record.path,
+        record.protocol,
+        record.status,
+        // No bytes are written as `-`, like web servers do
+        match record.bytes {
+            0 => "-".to_string(),
+            bytes => bytes.to_string(),
+        }
+    )
+}
+
+// Gateway specific fields, appended after the standard fields so log parsers keep working
+fn gateway_fields(record: &AccessLogRecord) -> String {
+    format!(
+        " entry=\"{}\" operation_id=\"{}\" upstream_status={} duration_ms={:.3} upstream_duration_ms={}",
+        record.entry.as_deref().unwrap_or("-"),
+        record.operation_id.as_deref().unwrap_or("-"),
+        record.upstream_status.map(|status| status.to_string()).unwrap_or_else(|| "-".to_string()),
+        record.duration_ms,
+        record.upstream_duration_ms.map(|duration| format!("{duration:.3}")).unwrap_or_else(|| "-".to_string())
+    )
+}
+
+fn redact_query(uri: &Uri, redact_params: &[String]) -> String {
+    let query = match uri.query() {
+        Some(query) => query,
+        None => return uri.path().to_string(),
+    };
+
+    let query = query
+        .split('&')
+        .map(|pair| {
+            let name = pair.split_once('=').map(|(name, _)| name).unwrap_or(pair);
+
+            if redact_params.iter().any(|param| param.eq_ignore_ascii_case(name)) {
+                format!("{name}={REDACTED}")
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    format!("{}?{}", uri.path(), query)
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+}
+
+/// Only the allow-listed headers, with the values of the headers to redact replaced
+fn logged_headers(headers: &HeaderMap, log_headers: &[String], redact_headers: &[String]) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| log_headers.iter().any(|header| header.eq_ignore_ascii_case(name.as_str())))
+        .map(|(name, value)| {
+            let value = if redact_headers.iter().any(|header| header.eq_ignore_ascii_case(name.as_str())) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).to_string()
+            };
+
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AccessLogConfig, AccessLogFormat};
+    use crate::web::access_log::{format_record, log_requests, logged_headers, redact_query, AccessLogRecord};
+    use axum::body::{Body, HttpBody, StreamBody};
+    use axum::http::{HeaderMap, HeaderValue, Request, Uri};
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn redact_sensitive_query_params() {
+        let uri = Uri::from_static("/pets?limit=10&API_KEY=secret&flag");
+
+        assert_eq!(
+            "/pets?limit=10&API_KEY=[REDACTED]&flag",
+            redact_query(&uri, &["api_key".to_string()])
+        );
+    }
+
+    #[test]
+    fn log_allowed_headers_only() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer secret"));
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert("accept", HeaderValue::from_static("application/json"));
+
+        let logged = logged_headers(&headers, &["Accept".to_string(), "authorization".to_string()], &["Authorization".to_string()]);
+
+        assert_eq!(2, logged.len());
+        assert_eq!("[REDACTED]", logged["authorization"]);
+        assert_eq!("application/json", logged["accept"]);
+    }
+
+    #[test]
+    fn format_combined_log_line() {
+        let line = format_record(&record(), &AccessLogFormat::Combined);
+
+        assert_eq!(
+            "10.0.0.1 - - [10/Oct/2022:13:55:36 +0000] \"GET /pets/1 HTTP/1.1\" 200 42 \"-\" \"curl/7.0\" \
+            entry=\"petstore\" operation_id=\"getPetById\" upstream_status=200 duration_ms=12.500 upstream_duration_ms=10.000",
+            line
+        );
+    }
+
+    #[tokio::test]
+    async fn pass_response_body_through() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let writer = Arc::clone(&log);
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt().with_writer(move || CapturedLog(Arc::clone(&writer))).finish(),
+        );
+        let config = AccessLogConfig { format: AccessLogFormat::Json, ..Default::default() };
+        let router = Router::new()
+            .route("/pets", get(|| async { "[]" }))
+            .route("/chunked", get(|| async {
+                StreamBody::new(futures::stream::iter(["[{\"id\": 1},", "{\"id\": 2}]"].map(Ok::<_, std::io::Error>)))
+            }))
+            .layer(from_fn_with_state(Arc::new(config), log_requests));
+
+        let response = router.clone().oneshot(Request::get("/pets").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(Some(2), response.body().size_hint().exact());
+        assert_eq!("[]", hyper::body::to_bytes(response.into_body()).await.unwrap());
+
+        let response = router.oneshot(Request::get("/chunked").body(Body::empty()).unwrap()).await.unwrap();
+        assert!(response.headers().get("content-length").is_none());
+        assert_eq!(21, hyper::body::to_bytes(response.into_body()).await.unwrap().len());
+
+        let log = String::from_utf8(log.lock().unwrap().clone()).unwrap();
+        assert!(log.contains(r#""bytes":2,"#));
+        assert!(log.contains(r#""bytes":21,"#));
+    }
+
+    struct CapturedLog(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn format_json_log_line() {
+        let line = format_record(&record(), &AccessLogFormat::Json);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!("10.0.0.1", json["client_ip"]);
+        assert_eq!("petstore", json["entry"]);
+        assert_eq!("getPetById", json["operation_id"]);
+        assert_eq!(200, json["upstream_status"]);
+        assert_eq!(42, json["bytes"]);
+    }
+
+    fn record() -> AccessLogRecord {
+        AccessLogRecord {
+            timestamp: Utc.with_ymd_and_hms(2022, 10, 10, 13, 55, 36).unwrap(),
+            client_ip: Some("10.0.0.1".to_string()),
+            method: "GET".to_string(),
+            path: "/pets/1".to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: 42,
+            entry: Some("petstore".to_string()),
+            operation_id: Some("getPetById".to_string()),
+            upstream_status: Some(200),
+            duration_ms: 12.5,
+            upstream_duration_ms: Some(10.0),
+            referer: None,
+            user_agent: Some("curl/7.0".to_string()),
+            headers: BTreeMap::new(),
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use crate::config::{AdminConfig, Config, ConfigError, OpenApiConfig};
+use crate::gateway::diff::SpecChanges;
+use crate::gateway::lint::Diagnostic;
+use crate::gateway::{GatewayEntry, Route};
+use crate::openapi::Parameter;
+use crate::web::handler::matching_route_with_least_matching_parameters;
+use crate::web::deprecation::{DeprecationUsage, UsageView};
+use crate::web::HttpClient;
+use crate::gateway::reload::HttpReloader;
+use crate::{fetch_entry, FetchError, RwConfig, RwGatewayEntries};
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use axum::middleware::{from_fn_with_state, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_macros::FromRef;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// What happens to entries changed through the admin API
+#[derive(Clone)]
+enum Persist {
+    /// Without config file they are only kept in memory
+    Memory,
+    /// Written to the config file
+    File(Arc<PathBuf>),
+    /// The config file is leading, changes would be undone when it is reloaded
+    Rejected,
+}
+
+#[derive(Clone, FromRef)]
+struct AdminState {
+    entries: RwGatewayEntries,
+    client: HttpClient,
+    reloader: Arc<HttpReloader>,
+    config: RwConfig,
+    usage: Arc<DeprecationUsage>,
+    persist: Persist,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdminError {
+    #[error("Entry not found: {0}")]
+    NotFound(String),
+    #[error("Entry already exists: {0}")]
+    Conflict(String),
+    #[error("{0}")]
+    InvalidConfig(#[from] ConfigError),
+    #[error("{0}")]
+    Fetch(#[from] FetchError),
+    #[error("Persisting config failed: {0}")]
+    Persist(ConfigError),
+    #[error("No pending changes for entry: {0}")]
+    NothingPending(String),
+    #[error("Entries are read from the config file, enable admin persist to change them")]
+    NotPersisted,
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            AdminError::NotFound(_) => StatusCode::NOT_FOUND,
+            AdminError::Conflict(_) | AdminError::NothingPending(_) | AdminError::NotPersisted => StatusCode::CONFLICT,
+            AdminError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AdminError::Fetch(_) => StatusCode::BAD_GATEWAY,
+            AdminError::Persist(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct EntryView {
+    pub name: String,
+    pub slug: String,
+    pub url: String,
+    pub upstream: String,
+    pub optional: bool,
+    pub loaded: bool,
+    pub stale: bool,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    pub failing_since: Option<DateTime<Utc>>,
+    pub retry_after: Option<DateTime<Utc>>,
+    pub content_hash: Option<String>,
+    /// Breaking changes held back until acknowledged
+    pub pending_changes: Option<SpecChanges>,
+    /// Problems found in the OpenAPI file when it was loaded
+    pub diagnostics: Vec<Diagnostic>,
+    pub routes: Vec<RouteView>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RouteView {
+    pub method: String,
+    pub template: String,
+    pub regex: String,
+    pub operation_id: Option<String>,
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExplainQuery {
+    pub method: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Explanation {
+    pub method: String,
+    pub path: String,
+    pub entry: Option<String>,
+    pub upstream: Option<String>,
+    pub route: Option<RouteView>,
+}
+
+/// Admin routes, every request requires `Authorization: Bearer <token>`
+pub fn admin_router(
+    entries: RwGatewayEntries,
+    client: HttpClient,
+    reloader: Arc<HttpReloader>,
+    shared_config: RwConfig,
+    usage: Arc<DeprecationUsage>,
+    config: &AdminConfig,
+    config_path: Option<PathBuf>,
+) -> Router {
+    let persist = match (config.persist, config_path) {
+        (true, Some(config_path)) => Persist::File(Arc::new(config_path)),
+        (true, None) => {
+            tracing::warn!("Admin changes can not be persisted, no config file is used");
+            Persist::Memory
+        }
+        (false, Some(_)) => Persist::Rejected,
+        (false, None) => Persist::Memory,
+    };
+
+    Router::new()
+        .route("/entries", get(list_entries_handler).post(create_entry_handler))
+        .route("/entries/:name", get(entry_handler).put(update_entry_handler).delete(delete_entry_handler))
+        .route("/entries/:name/reload", post(reload_entry_handler))
+        .route("/entries/:name/history", get(history_handler))
+        .route("/entries/:name/acknowledge", post(acknowledge_handler))
+        .route("/reload", post(reload_handler))
+        .route("/explain", get(explain_handler))
+        .route("/deprecations", get(deprecations_handler))
+        .route_layer(from_fn_with_state(Arc::new(config.token.clone()), require_token))
+        .with_state(AdminState { entries, client, reloader, config: shared_config, usage, persist })
+}
+
+async fn require_token(
+    State(token): State<Arc<String>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let authorized = req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|provided| constant_time_eq(provided.trim().as_bytes(), token.as_bytes()))
+        .unwrap_or(false);
+
+    if authorized {
+        next.run(req).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+async fn list_entries_handler(
+    State(entries): State<RwGatewayEntries>,
+) -> Json<Vec<EntryView>> {
+    let entries = entries.read().await;
+
+    Json(entries.iter().map(EntryView::from).collect())
+}
+
+async fn entry_handler(
+    State(entries): State<RwGatewayEntries>,
+    Path(name): Path<String>,
+) -> Result<Json<EntryView>, StatusCode> {
+    let entries = entries.read().await;
+
+    entries.iter()
+        .find(|entry| entry.config.name == name)
+        .map(|entry| Json(EntryView::from(entry)))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn create_entry_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(client): State<HttpClient>,
+    State(gateway_config): State<RwConfig>,
+    State(persist): State<Persist>,
+    Json(config): Json<OpenApiConfig>,
+) -> Result<(StatusCode, Json<EntryView>), AdminError> {
+    persist.check()?;
+    config.validate()?;
+    if entries.read().await.iter().any(|entry| conflicts(&entry.config, &config)) {
+        return Err(AdminError::Conflict(config.name));
+    }
+
+    // Fetch without holding the lock, requests keep being served meanwhile
+    let entry = fetch_entry(&client, &config).await?;
+    entry.warn_on_shadowed_routes(&gateway_config.read().await.health.paths());
+
+    let mut entries = entries.write().await;
+    if entries.iter().any(|entry| conflicts(&entry.config, &config)) {
+        return Err(AdminError::Conflict(config.name));
+    }
+
+    tracing::info!("Admin added entry: {}", config.name);
+    let view = EntryView::from(&entry);
+    entries.push(entry);
+    persist.store(&entries)?;
+
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+async fn update_entry_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(client): State<HttpClient>,
+    State(gateway_config): State<RwConfig>,
+    State(persist): State<Persist>,
+    Path(name): Path<String>,
+    Json(config): Json<OpenApiConfig>,
+) -> Result<Json<EntryView>, AdminError> {
+    persist.check()?;
+    config.validate()?;
+    check_update(&entries.read().await, &name, &config)?;
+
+    let entry = fetch_entry(&client, &config).await?;
+    entry.warn_on_shadowed_routes(&gateway_config.read().await.health.paths());
+
+    let mut entries = entries.write().await;
+    check_update(&entries, &name, &config)?;
+
+    tracing::info!("Admin updated entry: {name}");
+    let view = EntryView::from(&entry);
+    if let Some(existing) = entries.iter_mut().find(|entry| entry.config.name == name) {
+        *existing = entry;
+    }
+    persist.store(&entries)?;
+
+    Ok(Json(view))
+}
+
+async fn delete_entry_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(persist): State<Persist>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, AdminError> {
+    persist.check()?;
+    let mut entries = entries.write().await;
+
+    let index = entries.iter()
+        .position(|entry| entry.config.name == name)
+        .ok_or_else(|| AdminError::NotFound(name.clone()))?;
+
+    tracing::info!("Admin removed entry: {name}");
+    entries.remove(index);
+    persist.store(&entries)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reloads all entries right away, regardless of their schedule and backoff
+async fn reload_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(reloader): State<Arc<HttpReloader>>,
+    State(config): State<RwConfig>,
+) -> Json<Vec<EntryView>> {
+    tracing::info!("Admin reload of all entries");
+    let settings = config.read().await.reload.clone();
+    reloader.reload_where(&entries, &settings, |_| true).await;
+
+    Json(entries.read().await.iter().map(EntryView::from).collect())
+}
+
+async fn reload_entry_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(reloader): State<Arc<HttpReloader>>,
+    State(config): State<RwConfig>,
+    Path(name): Path<String>,
+) -> Result<Json<EntryView>, AdminError> {
+    if !entries.read().await.iter().any(|entry| entry.config.name == name) {
+        return Err(AdminError::NotFound(name));
+    }
+
+    tracing::info!("Admin reload of entry: {name}");
+    let settings = config.read().await.reload.clone();
+    reloader.reload_where(&entries, &settings, |entry| entry.config.name == name).await;
+
+    entries.read().await
+        .iter()
+        .find(|entry| entry.config.name == name)
+        .map(|entry| Json(EntryView::from(entry)))
+        .ok_or(AdminError::NotFound(name))
+}
+
+async fn history_handler(
+    State(entries): State<RwGatewayEntries>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<SpecChanges>>, AdminError> {
+    let entries = entries.read().await;
+
+    entries.iter()
+        .find(|entry| entry.config.name == name)
+        .map(|entry| Json(entry.changes.history.iter().cloned().collect()))
+        .ok_or(AdminError::NotFound(name))
+}
+
+/// Applies the breaking changes held back for an entry
+async fn acknowledge_handler(
+    State(entries): State<RwGatewayEntries>,
+    Path(name): Path<String>,
+) -> Result<Json<EntryView>, AdminError> {
+    let mut entries = entries.write().await;
+    let entry = entries.iter_mut()
+        .find(|entry| entry.config.name == name)
+        .ok_or_else(|| AdminError::NotFound(name.clone()))?;
+
+    if !entry.acknowledge() {
+        return Err(AdminError::NothingPending(name));
+    }
+
+    tracing::info!("Admin acknowledged breaking changes to entry: {name}");
+    Ok(Json(EntryView::from(&*entry)))
+}
+
+/// The entry must exist, and a rename may not collide with another entry
+fn check_update(entries: &[GatewayEntry], name: &str, config: &OpenApiConfig) -> Result<(), AdminError> {
+    if !entries.iter().any(|entry| entry.config.name == name) {
+        return Err(AdminError::NotFound(name.to_string()));
+    }
+
+    if entries.iter().any(|entry| entry.config.name != name && conflicts(&entry.config, config)) {
+        return Err(AdminError::Conflict(config.name.clone()));
+    }
+
+    Ok(())
+}
+
+/// Entries are addressed by name in the admin API and by slug in URLs of the documentation
+fn conflicts(existing: &OpenApiConfig, config: &OpenApiConfig) -> bool {
+    existing.name == config.name || existing.slug() == config.slug()
+}
+
+impl Persist {
+    fn check(&self) -> Result<(), AdminError> {
+        match self {
+            Persist::Rejected => Err(AdminError::NotPersisted),
+            _ => Ok(()),
+        }
+    }
+
+    fn store(&self, entries: &[GatewayEntry]) -> Result<(), AdminError> {
+        if let Persist::File(path) = self {
+            let configs: Vec<OpenApiConfig> = entries.iter()
+                .filter(|entry| entry.config.directory.is_none())
+                .map(|entry| entry.config.clone())
+                .collect();
+
+            Config::persist_openapi_urls(path.as_path(), &configs).map_err(AdminError::Persist)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Requests to deprecated operations per consumer, since the gateway started
+async fn deprecations_handler(
+    State(usage): State<Arc<DeprecationUsage>>,
+) -> Json<Vec<UsageView>> {
+    Json(usage.usage())
+}
+
+async fn explain_handler(
+    State(entries): State<RwGatewayEntries>,
+    Query(query): Query<ExplainQuery>,
+) -> Json<Explanation> {
+    let entries = entries.read().await;
+
+    Json(explain(&entries, query))
+}
+
+fn explain(entries: &[GatewayEntry], query: ExplainQuery) -> Explanation {
+    let matched = matching_route_with_least_matching_parameters(&query.path, &query.method, entries);
+
+    Explanation {
+        entry: matched.map(|(entry, _)| entry.config.name.clone()),
+        upstream: matched.map(|(entry, _)| entry.config.upstream()),
+        route: matched.map(|(_, route)| RouteView::from(route)),
+        method: query.method,
+        path: query.path,
+    }
+}
+
+impl From<&GatewayEntry> for EntryView {
+    fn from(entry: &GatewayEntry) -> Self {
+        EntryView {
+            name: entry.config.name.clone(),
+            slug: entry.config.slug(),
+            url: entry.config.url.clone(),
+            upstream: entry.config.upstream(),
+            optional: entry.config.optional,
+            loaded: entry.is_loaded(),
+            stale: entry.is_stale(),
+            last_fetch: entry.status.last_fetch,
+            last_success: entry.status.last_success,
+            last_error: entry.status.last_error.clone(),
+            consecutive_failures: entry.status.consecutive_failures,
+            failing_since: entry.status.failing_since,
+            retry_after: entry.status.retry_after,
+            content_hash: entry.openapi_file.as_ref().map(|file| file.content_hash.clone()),
+            pending_changes: entry.changes.pending.as_ref().map(|pending| pending.changes.clone()),
+            diagnostics: entry.openapi_file.as_ref().map(|file| file.diagnostics.clone()).unwrap_or_default(),
+            routes: entry.routes.iter().map(RouteView::from).collect(),
+        }
+    }
+}
+
+impl From<&Route> for RouteView {
+    fn from(route: &Route) -> Self {
+        RouteView {
+            method: route.method.clone(),
+            template: route.template.clone(),
+            regex: route.uri_regex.to_string(),
+            operation_id: route.operation_id.clone(),
+            parameters: route.path_parameters.clone(),
+        }
+    }
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left.iter().zip(right).fold(0u8, |diff, (left, right)| diff | (left ^ right)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{AdminConfig, Config, OpenApiConfig};
+    use crate::gateway::openapi::{parse_openapi, ContentType};
+    use crate::gateway::reload::{HttpFetcher, Reloader, SystemClock};
+    use crate::gateway::{EntryStatus, GatewayEntry, Route};
+    use crate::web::admin::{admin_router, explain, ExplainQuery};
+    use crate::web::HttpClient;
+    use crate::RwGatewayEntries;
+    use axum::body::Body;
+    use axum::http::{Request, Response, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use chrono::Utc;
+    use regex::Regex;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    const SPEC: &str = r#"{"openapi": "3.0.0", "paths": {"/users/{id}": {"get": {"parameters": [{"name": "id", "in": "path"}]}}}}"#;
+
+    #[test]
+    fn explain_matching_route() {
+        let entries = vec![entry()];
+
+        let explanation = explain(&entries, ExplainQuery { method: "get".to_string(), path: "/pets/1".to_string() });
+
+        assert_eq!(Some("petstore".to_string()), explanation.entry);
+        assert_eq!(Some("https://petstore.swagger.io".to_string()), explanation.upstream);
+        assert_eq!("/pets/{id}", explanation.route.unwrap().template);
+
+        let explanation = explain(&entries, ExplainQuery { method: "delete".to_string(), path: "/pets/1".to_string() });
+        assert!(explanation.entry.is_none());
+    }
+
+    #[tokio::test]
+    async fn reject_requests_without_token() {
+        let router = router(Arc::new(RwLock::new(vec![entry()])), None, false);
+
+        let unauthorized = router.clone()
+            .oneshot(Request::get("/entries").header("authorization", "Bearer wrong").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, unauthorized.status());
+
+        let authorized = send(&router, Request::get("/entries"), Body::empty()).await;
+        assert_eq!(StatusCode::OK, authorized.status());
+    }
+
+    #[tokio::test]
+    async fn create_update_and_delete_entries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/openapi.json", listener.local_addr().unwrap());
+        let upstream = Router::new().route("/openapi.json", get(|| async { SPEC }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(upstream.into_make_service()));
+
+        let config_path = std::env::temp_dir().join(format!("openapi-gateway-admin-{}.toml", std::process::id()));
+        std::fs::write(&config_path, "reload_cron = \"* * * * *\"\nopenapi_urls = []\n").unwrap();
+
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(vec![entry()]));
+        let router = router(Arc::clone(&entries), Some(config_path.clone()), true);
+
+        let created = send(&router, Request::post("/entries"), json_body("users", &url)).await;
+        assert_eq!(StatusCode::CREATED, created.status());
+        assert_eq!(1, entries.read().await[1].routes.len());
+
+        let conflict = send(&router, Request::post("/entries"), json_body("users", &url)).await;
+        assert_eq!(StatusCode::CONFLICT, conflict.status());
+
+        let invalid = send(&router, Request::put("/entries/users"), json_body("users", "ftp://example.com")).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, invalid.status());
+
+        let renamed = send(&router, Request::put("/entries/users"), json_body("accounts", &url)).await;
+        assert_eq!(StatusCode::OK, renamed.status());
+        assert_eq!("accounts", entries.read().await[1].config.name);
+
+        let persisted = Config::parse_from_file(&config_path).unwrap();
+        assert_eq!(vec!["petstore", "accounts"], persisted.openapi_urls.iter().map(|config| config.name.as_str()).collect::<Vec<_>>());
+
+        let deleted = send(&router, Request::delete("/entries/petstore"), Body::empty()).await;
+        assert_eq!(StatusCode::NO_CONTENT, deleted.status());
+        assert_eq!(1, entries.read().await.len());
+
+        let missing = send(&router, Request::delete("/entries/petstore"), Body::empty()).await;
+        assert_eq!(StatusCode::NOT_FOUND, missing.status());
+
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reject_changes_undone_by_config_reload() {
+        let config_path = std::env::temp_dir().join(format!("openapi-gateway-admin-not-persisted-{}.toml", std::process::id()));
+        std::fs::write(&config_path, "reload_cron = \"* * * * *\"\nopenapi_urls = []\n").unwrap();
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(vec![entry()]));
+        let router = router(Arc::clone(&entries), Some(config_path.clone()), false);
+
+        let created = send(&router, Request::post("/entries"), json_body("users", "http://users/openapi.json")).await;
+        assert_eq!(StatusCode::CONFLICT, created.status());
+        let deleted = send(&router, Request::delete("/entries/petstore"), Body::empty()).await;
+        assert_eq!(StatusCode::CONFLICT, deleted.status());
+        assert_eq!(1, entries.read().await.len());
+
+        std::fs::remove_file(config_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn force_reload_of_entries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/openapi.json", listener.local_addr().unwrap());
+        let upstream = Router::new().route("/openapi.json", get(|| async { SPEC }));
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(upstream.into_make_service()));
+
+        // Backing off after a failure, a forced reload ignores that
+        let mut users = GatewayEntry::unloaded(OpenApiConfig { name: "users".to_string(), url, reload: false, ..Default::default() });
+        users.status.record_failure("HTTP error".to_string(), Utc::now(), chrono::Duration::hours(1));
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(vec![users]));
+        let router = router(Arc::clone(&entries), None, false);
+
+        let reloaded = send(&router, Request::post("/entries/users/reload"), Body::empty()).await;
+        assert_eq!(StatusCode::OK, reloaded.status());
+        assert!(entries.read().await[0].is_loaded());
+        assert_eq!(0, entries.read().await[0].status.consecutive_failures);
+
+        let missing = send(&router, Request::post("/entries/orders/reload"), Body::empty()).await;
+        assert_eq!(StatusCode::NOT_FOUND, missing.status());
+
+        let all = send(&router, Request::post("/reload"), Body::empty()).await;
+        assert_eq!(StatusCode::OK, all.status());
+    }
+
+    #[tokio::test]
+    async fn acknowledge_breaking_changes() {
+        let config = OpenApiConfig {
+            name: "users".to_string(),
+            url: "http://users/openapi.json".to_string(),
+            block_breaking_changes: true,
+            ..Default::default()
+        };
+        let mut users = parse_openapi(ContentType::JSON, config.clone(), SPEC.as_bytes()).unwrap();
+        users.update(parse_openapi(ContentType::JSON, config, br#"{"openapi": "3.0.0", "paths": {}}"#).unwrap(), Utc::now());
+        let entries: RwGatewayEntries = Arc::new(RwLock::new(vec![users]));
+        let router = router(Arc::clone(&entries), None, false);
+
+        let acknowledged = send(&router, Request::post("/entries/users/acknowledge"), Body::empty()).await;
+        assert_eq!(StatusCode::OK, acknowledged.status());
+        assert!(entries.read().await[0].routes.is_empty());
+
+        let nothing_pending = send(&router, Request::post("/entries/users/acknowledge"), Body::empty()).await;
+        assert_eq!(StatusCode::CONFLICT, nothing_pending.status());
+
+        let history = send(&router, Request::get("/entries/users/history"), Body::empty()).await;
+        let body = hyper::body::to_bytes(history.into_body()).await.unwrap();
+        let history: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("operation_removed", history[0]["changes"][0]["kind"]);
+    }
+
+    fn router(entries: RwGatewayEntries, config_path: Option<PathBuf>, persist: bool) -> Router {
+        let config: Config = toml::from_str("reload_cron = \"* * * * *\"\nopenapi_urls = []\n").unwrap();
+
+        admin_router(
+            entries,
+            HttpClient::new(),
+            Arc::new(Reloader::new(HttpFetcher::new(HttpClient::new()), SystemClock, None)),
+            Arc::new(RwLock::new(config)),
+            Arc::default(),
+            &AdminConfig { token: "secret".to_string(), port: None, path: "/admin".to_string(), persist },
+            config_path,
+        )
+    }
+
+    async fn send(router: &Router, request: axum::http::request::Builder, body: Body) -> Response<axum::body::BoxBody> {
+        router.clone()
+            .oneshot(
+                request
+                    .header("authorization", "Bearer secret")
+                    .header("content-type", "application/json")
+                    .body(body)
+                    .unwrap()
+            )
+            .await
+            .unwrap()
+    }
+
+    fn json_body(name: &str, url: &str) -> Body {
+        Body::from(serde_json::to_vec(&OpenApiConfig { name: name.to_string(), url: url.to_string(), ..Default::default() }).unwrap())
+    }
+
+    fn entry() -> GatewayEntry {
+        GatewayEntry {
+            config: OpenApiConfig {
+                name: "petstore".to_string(),
+                url: "https://petstore.swagger.io/v2/swagger.json".to_string(),
+                ..Default::default()
+            },
+            openapi_file: None,
+            routes: vec![Route {
+                uri_regex: Regex::new("^/pets/[^/]*$").unwrap(),
+                template: "/pets/{id}".to_string(),
+                method: "get".to_string(),
+                operation_id: Some("getPetById".to_string()),
+                path_parameters: vec![],
+                deprecated: false,
+                sunset: None,
+            }],
+            status: EntryStatus::succeeded(Utc::now()),
+            changes: Default::default(),
+        }
+    }
+}
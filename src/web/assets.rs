@@ -0,0 +1,154 @@
+use crate::gateway::openapi::content_hash;
+use axum::body::Body;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, Response, StatusCode};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Served under `/docs`
+pub const SWAGGER_UI: &str = "swagger-ui";
+/// Served under `/redoc`
+pub const REDOC: &str = "redoc";
+
+#[cfg(feature = "embedded-ui")]
+macro_rules! embed {
+    ($($path:literal),* $(,)?) => {
+        &[$(($path, include_bytes!(concat!("../../", $path)))),*]
+    };
+}
+
+/// Assets compiled into the binary, without the source maps and ES module bundles the pages do not load
+#[cfg(feature = "embedded-ui")]
+const EMBEDDED: &[(&str, &[u8])] = embed!(
+    "swagger-ui/index.html",
+    "swagger-ui/index.css",
+    "swagger-ui/swagger-ui.css",
+    "swagger-ui/swagger-ui-bundle.js",
+    "swagger-ui/swagger-ui-standalone-preset.js",
+    "swagger-ui/swagger-initializer.js",
+    "swagger-ui/oauth2-redirect.html",
+    "swagger-ui/favicon-16x16.png",
+    "swagger-ui/favicon-32x32.png",
+    "redoc/index.html",
+);
+
+/// File of a UI, from `ui_dir` when it has the file and otherwise the built-in one
+pub async fn load(ui: &str, file: &str, ui_dir: Option<&Path>) -> Option<Cow<'static, [u8]>> {
+    // Hidden files and anything outside the directory of the UI are never served
+    if file.contains('\\') || file.split('/').any(|segment| segment.is_empty() || segment.starts_with('.')) {
+        return None;
+    }
+
+    if let Some(ui_dir) = ui_dir {
+        if let Ok(contents) = tokio::fs::read(ui_dir.join(ui).join(file)).await {
+            return Some(Cow::Owned(contents));
+        }
+    }
+
+    built_in(ui, file).await
+}
+
+#[cfg(feature = "embedded-ui")]
+async fn built_in(ui: &str, file: &str) -> Option<Cow<'static, [u8]>> {
+    EMBEDDED.iter()
+        .find(|(path, _)| path.strip_prefix(ui).and_then(|path| path.strip_prefix('/')) == Some(file))
+        .map(|(_, contents)| Cow::Borrowed(*contents))
+}
+
+/// Without embedded assets they are read from next to the executable, or else the working directory
+#[cfg(not(feature = "embedded-ui"))]
+async fn built_in(ui: &str, file: &str) -> Option<Cow<'static, [u8]>> {
+    let dir = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|exe_dir| exe_dir.join(ui)))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| ui.into());
+
+    tokio::fs::read(dir.join(file)).await.ok().map(Cow::Owned)
+}
+
+pub fn content_type(file: &str) -> &'static str {
+    match file.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("yaml") | Some("yml") => "application/yaml",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Pages are revalidated on every request, so they pick up changed assets, which are cached for an hour
+pub fn asset_response(file: &str, contents: Cow<'static, [u8]>, headers: &HeaderMap) -> Response<Body> {
+    let etag = format!("\"{}\"", content_hash(&contents));
+    let not_modified = headers.get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == "*" || tag.trim().trim_start_matches("W/") == etag))
+        .unwrap_or(false);
+
+    let cache_control = match file.ends_with(".html") {
+        true => "no-cache",
+        false => "public, max-age=3600",
+    };
+
+    let response = Response::builder()
+        .header(ETAG, &etag)
+        .header(CACHE_CONTROL, cache_control);
+    match not_modified {
+        true => response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap(),
+        false => response
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, content_type(file))
+            .body(Body::from(contents))
+            .unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web::assets::{asset_response, load, REDOC, SWAGGER_UI};
+    use axum::http::{HeaderMap, StatusCode};
+
+    #[tokio::test]
+    async fn override_built_in_assets() {
+        let dir = std::env::temp_dir().join(format!("openapi-gateway-ui-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(SWAGGER_UI)).unwrap();
+        std::fs::write(dir.join(SWAGGER_UI).join("index.css"), "body { background: purple; }").unwrap();
+
+        let css = load(SWAGGER_UI, "index.css", Some(&dir)).await.unwrap();
+        assert_eq!(b"body { background: purple; }", css.as_ref());
+
+        // Not overridden
+        let index = load(SWAGGER_UI, "index.html", Some(&dir)).await.unwrap();
+        assert!(String::from_utf8_lossy(&index).contains("swagger-config.json"));
+        assert!(load(REDOC, "index.html", Some(&dir)).await.is_some());
+
+        assert!(load(SWAGGER_UI, "missing.js", Some(&dir)).await.is_none());
+        assert!(load(SWAGGER_UI, "../redoc/index.html", None).await.is_none());
+        assert!(load(SWAGGER_UI, ".env", None).await.is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn assets_with_etag_and_caching() {
+        let contents = load(SWAGGER_UI, "swagger-ui.css", None).await.unwrap();
+        let response = asset_response("swagger-ui.css", contents.clone(), &HeaderMap::new());
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!("text/css; charset=utf-8", response.headers()["content-type"]);
+        assert_eq!("public, max-age=3600", response.headers()["cache-control"]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("if-none-match", response.headers()["etag"].clone());
+        let response = asset_response("swagger-ui.css", contents, &headers);
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+
+        let index = load(SWAGGER_UI, "index.html", None).await.unwrap();
+        let response = asset_response("index.html", index, &HeaderMap::new());
+        assert_eq!("text/html; charset=utf-8", response.headers()["content-type"]);
+        assert_eq!("no-cache", response.headers()["cache-control"]);
+    }
+}
@@ -0,0 +1,150 @@
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, HeaderValue, Request};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// Entry, operation and consumer
+type UsageKey = (String, String, String);
+
+/// Consumers come from a request header, further ones are counted as `OTHER_CONSUMERS` to bound memory
+pub const MAX_USAGE_KEYS: usize = 1000;
+pub const OTHER_CONSUMERS: &str = "other";
+/// Longer consumer header values are cut off
+const MAX_CONSUMER_LENGTH: usize = 128;
+
+/// Requests to deprecated operations, per entry, operation and consumer
+#[derive(Default, Debug)]
+pub struct DeprecationUsage {
+    usage: Mutex<BTreeMap<UsageKey, (u64, DateTime<Utc>)>>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct UsageView {
+    pub entry: String,
+    /// Operation id, or method and path when the operation has none
+    pub operation: String,
+    pub consumer: String,
+    pub requests: u64,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl DeprecationUsage {
+    pub fn record(&self, entry: &str, operation: &str, consumer: &str, now: DateTime<Utc>) {
+        let mut usage = self.usage.lock().unwrap();
+        let mut key = (entry.to_string(), operation.to_string(), consumer.to_string());
+        if usage.len() >= MAX_USAGE_KEYS && !usage.contains_key(&key) {
+            key.2 = OTHER_CONSUMERS.to_string();
+        }
+        let counter = usage.entry(key).or_insert((0, now));
+
+        counter.0 += 1;
+        counter.1 = now;
+    }
+
+    pub fn usage(&self) -> Vec<UsageView> {
+        self.usage.lock().unwrap()
+            .iter()
+            .map(|((entry, operation, consumer), (requests, last_seen))| UsageView {
+                entry: entry.clone(),
+                operation: operation.clone(),
+                consumer: consumer.clone(),
+                requests: *requests,
+                last_seen: *last_seen,
+            })
+            .collect()
+    }
+}
+
+/// Value of the consumer header when configured and sent, the client IP otherwise
+pub fn consumer(req: &Request<Body>, consumer_header: Option<&str>) -> String {
+    consumer_header
+        .and_then(|header| req.headers().get(header))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(MAX_CONSUMER_LENGTH).collect())
+        .or_else(|| req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// `Deprecation: true`, and `Sunset` as HTTP date when `x-sunset` is a date
+pub fn insert_deprecation_headers(headers: &mut HeaderMap, sunset: Option<&str>) {
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+
+    if let Some(sunset) = sunset.and_then(http_date).and_then(|sunset| HeaderValue::from_str(&sunset).ok()) {
+        headers.insert("sunset", sunset);
+    }
+}
+
+/// Accepts RFC 3339, `YYYY-MM-DD` and HTTP dates
+fn http_date(sunset: &str) -> Option<String> {
+    let sunset = sunset.trim();
+    let date = DateTime::parse_from_rfc3339(sunset)
+        .or_else(|_| DateTime::parse_from_rfc2822(sunset))
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(sunset, "%Y-%m-%d").ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|date| Utc.from_utc_datetime(&date))
+        })?;
+
+    Some(date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::web::deprecation::{consumer, insert_deprecation_headers, DeprecationUsage, MAX_USAGE_KEYS, OTHER_CONSUMERS};
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Request};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn sunset_as_http_date() {
+        for sunset in ["2030-06-30", "2030-06-30T00:00:00Z", "Sun, 30 Jun 2030 00:00:00 GMT"] {
+            let mut headers = HeaderMap::new();
+            insert_deprecation_headers(&mut headers, Some(sunset));
+
+            assert_eq!("true", headers["deprecation"]);
+            assert_eq!("Sun, 30 Jun 2030 00:00:00 GMT", headers["sunset"]);
+        }
+
+        let mut headers = HeaderMap::new();
+        insert_deprecation_headers(&mut headers, Some("next year"));
+        assert!(headers.get("sunset").is_none());
+    }
+
+    #[test]
+    fn count_usage_per_consumer() {
+        let usage = DeprecationUsage::default();
+        let (first, last) = (Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(), Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        let request = Request::get("/pets").header("x-consumer", "billing").body(Body::empty()).unwrap();
+
+        usage.record("petstore", "listPets", &consumer(&request, Some("x-consumer")), first);
+        usage.record("petstore", "listPets", &consumer(&request, Some("x-consumer")), last);
+        usage.record("petstore", "listPets", &consumer(&request, None), first);
+
+        let usage = usage.usage();
+        assert_eq!(2, usage.len());
+        assert_eq!(("billing", 2, last), (usage[0].consumer.as_str(), usage[0].requests, usage[0].last_seen));
+        assert_eq!(("unknown", 1), (usage[1].consumer.as_str(), usage[1].requests));
+    }
+
+    #[test]
+    fn bound_number_of_consumers() {
+        let usage = DeprecationUsage::default();
+        let now = Utc::now();
+
+        for consumer in 0..MAX_USAGE_KEYS + 10 {
+            usage.record("petstore", "listPets", &consumer.to_string(), now);
+        }
+        usage.record("petstore", "listPets", "0", now);
+
+        let usage = usage.usage();
+        assert_eq!(MAX_USAGE_KEYS + 1, usage.len());
+        assert_eq!(2, usage.iter().find(|view| view.consumer == "0").unwrap().requests);
+        assert_eq!(10, usage.iter().find(|view| view.consumer == OTHER_CONSUMERS).unwrap().requests);
+    }
+}
@@ -1,42 +1,264 @@
+use crate::config::DeprecatedPolicy;
+use crate::gateway::merge::merge_openapi;
+use crate::gateway::openapi::{content_hash, ContentType};
+use crate::gateway::Route;
 use crate::ui::{SwaggerUiConfig, Url};
+use crate::web::assets;
+use crate::web::assets::asset_response;
+use crate::web::deprecation::{consumer, insert_deprecation_headers, DeprecationUsage};
 use crate::web::HttpClient;
-use crate::{GatewayEntry, RwGatewayEntries};
+use crate::{GatewayEntry, RwConfig, RwGatewayEntries};
 use axum::body::Body;
 use axum::extract::{State, Path};
-use axum::http::header::CONTENT_TYPE;
-use axum::http::{HeaderValue, Method, Request, Response, StatusCode, Uri};
+use axum::http::header::{ACCEPT, CONTENT_TYPE, ETAG, FORWARDED, HOST, IF_NONE_MATCH, LOCATION, VARY};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
 use axum::Json;
-use crate::config::CorsConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Response extension describing which entry and operation served the request.
+#[derive(Clone, Debug)]
+pub struct MatchedRoute {
+    pub entry: String,
+    pub operation_id: Option<String>,
+}
+
+/// Response extension describing the proxied upstream call.
+#[derive(Clone, Debug)]
+pub struct UpstreamResponse {
+    pub status: StatusCode,
+    pub duration: Duration,
+}
 
+/// Serves the OpenAPI file of an entry by slug as JSON or YAML, picked by a `.json` or `.yaml` suffix, the `Accept`
+/// header or else the format it was fetched in. Compressed by the gzip layer of the route.
+///
+/// The base64 encoded name the files used to be served at redirects to the slug.
 pub async fn swagger_def_handler(
     State(entries): State<RwGatewayEntries>,
+    State(config): State<RwConfig>,
     Path(def): Path<String>,
+    headers: HeaderMap,
 ) -> Response<Body> {
+    let public_url = public_url(config.read().await.public_url.as_deref(), &headers);
     let entries = entries.read().await;
 
-    let entry = entries
-        .iter()
-        .filter(|entry| base64::encode(entry.config.name.clone()) == def)
-        .last();
+    // Matched as a wildcard, since base64 names can have a `/`
+    let def = def.strip_prefix('/').unwrap_or(&def);
+    // Neither slugs nor base64 have a `.`, so a suffix is never part of them
+    let (def, suffix) = match def.rsplit_once('.') {
+        Some((def, extension @ "json")) => (def, Some((ContentType::JSON, extension))),
+        Some((def, extension @ ("yaml" | "yml"))) => (def, Some((ContentType::YAML, extension))),
+        _ => (def, None),
+    };
 
-    if let Some(entry) = entry {
-        if let Some(openapi_file) = &entry.openapi_file {
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(CONTENT_TYPE, openapi_file.content_type.clone())
-                .body(Body::from(entry.openapi_file.as_ref().unwrap().contents.clone()))
-                .unwrap()
-        } else {
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::empty())
-                .unwrap()
+    let entry = match entries.iter().find(|entry| entry.config.slug() == def) {
+        Some(entry) => entry,
+        None => {
+            return match entries.iter().find(|entry| BASE64.encode(&entry.config.name) == def) {
+                Some(entry) => {
+                    let extension = suffix.map(|(_, extension)| format!(".{extension}")).unwrap_or_default();
+                    Response::builder()
+                        .status(StatusCode::MOVED_PERMANENTLY)
+                        .header(LOCATION, format!("/docs/defs/{}{extension}", entry.config.slug()))
+                        .body(Body::empty())
+                        .unwrap()
+                }
+                None => status_response(StatusCode::NOT_FOUND),
+            };
         }
+    };
+    let Some(openapi_file) = entry.openapi_file.as_ref() else {
+        return status_response(StatusCode::NOT_FOUND);
+    };
+
+    let fetched_as = match openapi_file.content_type.as_str() {
+        "application/yaml" => ContentType::YAML,
+        _ => ContentType::JSON,
+    };
+    let accept = headers.get(ACCEPT).and_then(|accept| accept.to_str().ok());
+    let Some(format) = suffix.map(|(format, _)| format).or_else(|| negotiate(accept, fetched_as)) else {
+        return status_response(StatusCode::NOT_ACCEPTABLE);
+    };
+
+    let published = openapi_file.published(&public_url);
+    let (content_type, body) = match format {
+        ContentType::JSON => ("application/json", published),
+        ContentType::YAML => {
+            let yaml = serde_json::from_slice::<serde_json::Value>(&published).ok()
+                .and_then(|document| serde_yaml::to_string(&document).ok());
+            match yaml {
+                Some(yaml) => ("application/yaml", yaml.into_bytes()),
+                None => return status_response(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+    };
+
+    // Weak, as the same document is served gzipped as well
+    let etag = format!("W/\"{}\"", content_hash(&body));
+    let not_modified = headers.get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(',').any(|tag| tag.trim() == "*" || tag.trim().trim_start_matches("W/") == &etag[2..]))
+        .unwrap_or(false);
+
+    let response = Response::builder()
+        .header(ETAG, &etag)
+        .header(VARY, "accept, accept-encoding");
+    match not_modified {
+        true => response.status(StatusCode::NOT_MODIFIED).body(Body::empty()).unwrap(),
+        false => response
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap(),
+    }
+}
+
+/// Format most preferred by an `Accept` header, the one named more specifically or else `preferred` on a tie.
+/// `None` when neither JSON nor YAML is accepted.
+pub fn negotiate(accept: Option<&str>, preferred: ContentType) -> Option<ContentType> {
+    let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+        return Some(preferred);
+    };
+
+    // Media range and quality
+    let ranges: Vec<(String, f32)> = accept.split(',')
+        .map(|range| {
+            let mut parts = range.split(';');
+            let media_range = parts.next().unwrap_or("").trim().to_lowercase();
+            let quality = parts
+                .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+                .find_map(|quality| quality.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            (media_range, quality)
+        })
+        .collect();
+
+    // Quality and specificity of the most specific range matching one of the media types of a format
+    let quality = |media_types: &[&str]| -> (f32, u8) {
+        let specificity = |media_range: &str| match media_range {
+            "*/*" => Some(0),
+            range if media_types.contains(&range) => Some(2),
+            range => range.strip_suffix("/*")
+                .filter(|range_type| media_types.iter().any(|media_type| media_type.split('/').next() == Some(*range_type)))
+                .map(|_| 1),
+        };
+
+        ranges.iter()
+            .filter_map(|(media_range, quality)| specificity(media_range).map(|specificity| (specificity, *quality)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(specificity, quality)| (quality, specificity))
+            .unwrap_or((0.0, 0))
+    };
+
+    let other = match preferred {
+        ContentType::JSON => ContentType::YAML,
+        ContentType::YAML => ContentType::JSON,
+    };
+    let media_types = |format: ContentType| -> &'static [&'static str] {
+        match format {
+            ContentType::JSON => &["application/json"],
+            ContentType::YAML => &["application/yaml", "application/x-yaml", "text/yaml"],
+        }
+    };
+
+    let (preferred_quality, other_quality) = (quality(media_types(preferred)), quality(media_types(other)));
+    if preferred_quality.0 <= 0.0 && other_quality.0 <= 0.0 {
+        None
+    } else if other_quality.0 > preferred_quality.0 || (other_quality.0 == preferred_quality.0 && other_quality.1 > preferred_quality.1) {
+        Some(other)
     } else {
-        Response::builder()
-            .status(StatusCode::NOT_FOUND)
+        Some(preferred)
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// All entries combined into one OpenAPI document
+pub async fn merged_openapi_json_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(config): State<RwConfig>,
+    headers: HeaderMap,
+) -> Json<serde_json::Value> {
+    let public_url = public_url(config.read().await.public_url.as_deref(), &headers);
+
+    Json(merge_openapi(&entries.read().await, &public_url))
+}
+
+pub async fn merged_openapi_yaml_handler(
+    State(entries): State<RwGatewayEntries>,
+    State(config): State<RwConfig>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let public_url = public_url(config.read().await.public_url.as_deref(), &headers);
+    let merged = merge_openapi(&entries.read().await, &public_url);
+
+    match serde_yaml::to_string(&merged) {
+        Ok(yaml) => Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/yaml")
+            .body(Body::from(yaml))
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(Body::empty())
-            .unwrap()
+            .unwrap(),
+    }
+}
+
+/// Externally visible URL of the gateway, `public_url` when configured and otherwise derived from the
+/// `Forwarded` or `Host` header of the request. Empty when unknown, servers are relative then.
+pub fn public_url(configured: Option<&str>, headers: &HeaderMap) -> String {
+    if let Some(configured) = configured {
+        return configured.trim_end_matches('/').to_string();
+    }
+
+    // Only the proxy closest to the client is of interest
+    let forwarded: Vec<(String, String)> = headers.get(FORWARDED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|element| {
+            element.split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.trim().to_lowercase(), value.trim().trim_matches('"').to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let forwarded = |key: &str| forwarded.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone());
+
+    let host = forwarded("host").or_else(|| headers.get(HOST).and_then(|host| host.to_str().ok()).map(str::to_string));
+    match host {
+        Some(host) => format!("{}://{host}", forwarded("proto").unwrap_or_else(|| "http".to_string())),
+        None => String::new(),
+    }
+}
+
+/// Serves Redoc, paths without a file serve its index as it picks the OpenAPI file from the query
+pub async fn redoc_handler(State(config): State<RwConfig>, uri: Uri, headers: HeaderMap) -> Response<Body> {
+    let ui_dir = config.read().await.ui_dir.clone();
+    let file = uri.path().trim_start_matches("/redoc").trim_start_matches('/');
+
+    let asset = match file.is_empty() {
+        true => None,
+        false => assets::load(assets::REDOC, file, ui_dir.as_deref()).await.map(|contents| (file, contents)),
+    };
+    let asset = match asset {
+        Some(asset) => Some(asset),
+        None => assets::load(assets::REDOC, "index.html", ui_dir.as_deref()).await.map(|contents| ("index.html", contents)),
+    };
+
+    match asset {
+        Some((file, contents)) => asset_response(file, contents, &headers),
+        None => status_response(StatusCode::NOT_FOUND),
     }
 }
 
@@ -49,7 +271,7 @@ pub async fn swagger_conf_handler(
             .iter()
             .map(|entry| Url {
                 name: entry.config.name.clone(),
-                url: format!("/docs/defs/{}", base64::encode(entry.config.name.clone())),
+                url: format!("/docs/defs/{}.json", entry.config.slug()),
             })
             .collect(),
     };
@@ -59,10 +281,15 @@ pub async fn swagger_conf_handler(
 
 pub async fn gateway_handler(
     State(entries): State<RwGatewayEntries>,
-    State(global_cors_config): State<Option<CorsConfig>>,
+    State(config): State<RwConfig>,
     State(client): State<HttpClient>,
+    State(usage): State<Arc<DeprecationUsage>>,
     mut req: Request<Body>,
 ) -> Response<Body> {
+    let (global_cors_config, consumer_header, ui_dir) = {
+        let config = config.read().await;
+        (config.global_cors.clone(), config.consumer_header.clone(), config.ui_dir.clone())
+    };
     let entries = entries.read().await;
 
     let path = req.uri().path();
@@ -73,118 +300,79 @@ pub async fn gateway_handler(
         .unwrap_or(path);
 
     if req.method() == Method::GET && path.starts_with("/docs") {
-        let mut file = &path[5..].trim_start_matches("/");
-
-        if file.starts_with(".") || file.starts_with("..") {
+        // Relative links of the index resolve against `/docs/`
+        if path == "/docs" {
             return Response::builder()
-                .status(StatusCode::NOT_FOUND)
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(LOCATION, "/docs/")
                 .body(Body::empty())
                 .unwrap();
         }
 
-        if file.is_empty() {
-            file = &"index.html";
-        }
-
-        let mut builder = Response::builder().status(StatusCode::OK);
-
-        if file.ends_with(".json") {
-            builder = builder.header(CONTENT_TYPE, "application/json");
-        }
-
-        let exe_file = std::env::current_exe();
-        let exe_folder = exe_file
-            .as_ref()
-            .map(|val| val.as_path().parent());
-
-
-        let default = format!("swagger-ui/{}", file);
-        let file_path = if let Ok(Some(path)) = exe_folder {
-            let swagger_ui_folder = path.join("swagger-ui");
-
-            if swagger_ui_folder.exists() {
-                swagger_ui_folder.join(file)
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-            } else {
-                default
-            }
-        } else {
-            default
+        let file = match path.trim_start_matches("/docs").trim_start_matches('/') {
+            "" => "index.html",
+            file => file,
         };
 
-        tracing::info!("Loading from disk: {file_path}");
-        let content_type = if let Some(split) = file_path.rsplit_once(".") {
-            match split.1 {
-                "css" => "text/css",
-                "js" => "text/javascript",
-                "html" => "text/html",
-                "png" => "image/png",
-                "json" => "application/json",
-                "yml" => "application/yaml",
-                "yaml" => "application/yaml",
-                _ => "text/plain"
-            }
-        } else {
-            "text/plain"
+        return match assets::load(assets::SWAGGER_UI, file, ui_dir.as_deref()).await {
+            Some(contents) => asset_response(file, contents, req.headers()),
+            None => status_response(StatusCode::NOT_FOUND),
         };
-
-        let bytes = std::fs::read(file_path).unwrap();
-        return builder
-            .header(CONTENT_TYPE, content_type)
-            .body(Body::from(bytes))
-            .unwrap();
     }
 
     let entry = matching_route_with_least_matching_parameters(
         path,
         req.method().as_str(),
-        &*entries
+        &entries
     );
 
-    let req = if let Some(entry) = entry {
-        let entry_uri: &Uri = &entry.config.uri();
-
-        let uri = if let Some(port) = entry_uri.port_u16() {
-            format!(
-                "{}://{}:{}{}",
-                entry_uri.scheme_str().unwrap(),
-                entry_uri.host().unwrap(),
-                port,
-                path_query
-            )
-        } else {
-            format!(
-                "{}://{}{}",
-                entry_uri.scheme_str().unwrap(),
-                entry_uri.host().unwrap(),
-                path_query
-            )
-        };
+    let (req, matched, sunset) = if let Some((entry, route)) = entry {
+        let policy = entry.config.deprecated_operations;
+        if route.deprecated {
+            let operation = route.operation_id.clone().unwrap_or_else(|| format!("{} {}", route.method.to_uppercase(), route.template));
+            usage.record(&entry.config.name, &operation, &consumer(&req, consumer_header.as_deref()), Utc::now());
+
+            if policy == DeprecatedPolicy::Reject {
+                let mut response = Response::builder()
+                    .status(StatusCode::GONE)
+                    .extension(MatchedRoute { entry: entry.config.name.clone(), operation_id: route.operation_id.clone() })
+                    .body(Body::empty())
+                    .unwrap();
+                insert_deprecation_headers(response.headers_mut(), route.sunset.as_deref());
+
+                return response;
+            }
+        }
+        // Only deprecated operations of entries that announce deprecation
+        let sunset = (route.deprecated && policy == DeprecatedPolicy::Headers).then(|| route.sunset.clone());
+
+        let uri = format!("{}{}", entry.config.upstream(), path_query);
 
         *req.uri_mut() = Uri::try_from(uri).unwrap();
-        req.headers_mut().insert(
-            "host",
-            HeaderValue::from_str(entry.config.uri().host().unwrap()).unwrap(),
-        );
+        let host = HeaderValue::from_str(req.uri().host().unwrap()).unwrap();
+        req.headers_mut().insert("host", host);
+
+        let matched = MatchedRoute {
+            entry: entry.config.name.clone(),
+            operation_id: route.operation_id.clone(),
+        };
 
-        req
+        (req, matched, sunset)
     } else {
         let entry = entries
             .iter()
-            .filter(|val| val.contains_route(path))
-            .last();
+            .rfind(|val| val.contains_route(path));
 
         // If route is found and cors config is available create OK response
-        if let (Some(_), Some(global_cors_config)) = (entry, global_cors_config) {
+        if let (Some(entry), Some(global_cors_config)) = (entry, global_cors_config) {
             let mut response = Response::builder()
                 .status(StatusCode::OK)
+                .extension(MatchedRoute { entry: entry.config.name.clone(), operation_id: None })
                 .body(Body::empty())
                 .unwrap();
 
             // @TODO: Duplicated code, fix
-            let mut headers = response.headers_mut();
+            let headers = response.headers_mut();
             headers.insert("Access-Control-Allow-Origin", HeaderValue::from_str(&global_cors_config.allowed_origin).unwrap());
             headers.insert(
                 "Access-Control-Allow-Methods",
@@ -211,10 +399,55 @@ pub async fn gateway_handler(
     // Make sure to free up read lock
     drop(entries);
 
-    let mut response: Response<Body> = client.request(req).await.unwrap();
+    #[cfg(feature = "otlp")]
+    let (req, client_span) = {
+        let mut req = req;
+        let span_name = matched.operation_id.clone().unwrap_or_else(|| req.method().to_string());
+        let trace = req.extensions().get::<crate::telemetry::RequestTrace>().cloned();
+        let uri = req.uri().clone();
+        let client_span = trace.map(|trace| trace.start_client_span(&span_name, &uri, req.headers_mut()));
+
+        (req, client_span)
+    };
+
+    let upstream_start = Instant::now();
+    let mut response: Response<Body> = match client.request(req).await {
+        Ok(mut response) => {
+            let upstream = UpstreamResponse {
+                status: response.status(),
+                duration: upstream_start.elapsed(),
+            };
+            response.extensions_mut().insert(upstream);
+
+            response
+        }
+        Err(err) => {
+            tracing::error!("Upstream request failed: {err}");
+
+            Response::builder()
+                .status(StatusCode::BAD_GATEWAY)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    #[cfg(feature = "otlp")]
+    if let Some(mut client_span) = client_span {
+        client_span.set_attribute("http.status_code", response.status().as_u16() as i64);
+        if response.status().is_server_error() {
+            client_span.set_error();
+        }
+        client_span.end();
+    }
+
+    response.extensions_mut().insert(matched);
+
+    if let Some(sunset) = sunset {
+        insert_deprecation_headers(response.headers_mut(), sunset.as_deref());
+    }
 
     if let Some(global_cors_config) = global_cors_config {
-        let mut headers = response.headers_mut();
+        let headers = response.headers_mut();
         headers.insert("Access-Control-Allow-Origin", HeaderValue::from_str(&global_cors_config.allowed_origin).unwrap());
         headers.insert(
             "Access-Control-Allow-Methods",
@@ -233,11 +466,11 @@ pub async fn gateway_handler(
     response
 }
 
-fn matching_route_with_least_matching_parameters<'a>(
+pub fn matching_route_with_least_matching_parameters<'a>(
     path: &str,
     method: &str,
-    entries: &'a Vec<GatewayEntry>
-) -> Option<&'a GatewayEntry> {
+    entries: &'a [GatewayEntry]
+) -> Option<(&'a GatewayEntry, &'a Route)> {
     entries
         .iter()
         .filter_map(|entry| {
@@ -246,13 +479,9 @@ fn matching_route_with_least_matching_parameters<'a>(
                 .filter(|route| route.method.to_lowercase() == method.to_lowercase())
                 .min_by(|left, right| left.path_parameters.len().cmp(&right.path_parameters.len()));
 
-            match route {
-                Some(route) => Some((route, entry)),
-                None => None
-            }
+            route.map(|route| (entry, route))
         })
-        .min_by(|left, right| left.0.path_parameters.len().cmp(&right.0.path_parameters.len()))
-        .map(|route_entry| route_entry.1)
+        .min_by(|left, right| left.1.path_parameters.len().cmp(&right.1.path_parameters.len()))
 }
 
 #[cfg(test)]
@@ -261,43 +490,66 @@ mod tests {
     use crate::{GatewayEntry, OpenApiConfig};
     use crate::gateway::Route;
     use crate::openapi::Parameter;
-    use crate::web::handler::matching_route_with_least_matching_parameters;
+    use crate::gateway::openapi::{parse_openapi, ContentType};
+    use crate::web::handler::{matching_route_with_least_matching_parameters, negotiate, public_url, swagger_def_handler};
+    use crate::web::{AppState, HttpClient};
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+    use tower_http::compression::CompressionLayer;
 
     #[test]
     fn route_with_least_parameters() {
         let entry1 = GatewayEntry {
             config: OpenApiConfig {
                 name: "entry1".to_string(),
-                url: "".to_string()
+                ..Default::default()
             },
             openapi_file: None,
             routes: vec![
                 Route {
                     uri_regex: Regex::new(&escape("/foo/bar")).unwrap(),
+                    template: "/foo/bar".to_string(),
                     method: "GET".to_string(),
-                    path_parameters: vec![]
+                    operation_id: None,
+                    path_parameters: vec![],
+                    deprecated: false,
+                    sunset: None
                 },
-            ]
+            ],
+            status: Default::default(),
+            changes: Default::default()
         };
 
         let entry2 = GatewayEntry {
             config: OpenApiConfig {
                 name: "entry2".to_string(),
-                url: "".to_string()
+                ..Default::default()
             },
             openapi_file: None,
             routes: vec![
                 Route {
                     uri_regex: Regex::new("/foo/.*").unwrap(),
+                    template: "/foo/{par}".to_string(),
                     method: "GET".to_string(),
+                    operation_id: None,
                     path_parameters: vec![
                         Parameter {
                             name: "par".to_string(),
-                            in_type: "path".to_string()
+                            in_type: "path".to_string(),
+                            required: true
                         }
-                    ]
+                    ],
+                    deprecated: false,
+                    sunset: None
                 },
-            ]
+            ],
+            status: Default::default(),
+            changes: Default::default()
         };
 
         let entries = vec![entry1, entry2];
@@ -309,7 +561,7 @@ mod tests {
         );
 
         assert!(route.is_some());
-        assert_eq!("entry1", route.unwrap().config.name);
+        assert_eq!("entry1", route.unwrap().0.config.name);
 
 
         let route = matching_route_with_least_matching_parameters(
@@ -319,6 +571,87 @@ mod tests {
         );
 
         assert!(route.is_some());
-        assert_eq!("entry2", route.unwrap().config.name);
+        assert_eq!("entry2", route.unwrap().0.config.name);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn public_url_from_config_or_request() {
+        let mut headers = HeaderMap::new();
+        assert_eq!("", public_url(None, &headers));
+
+        headers.insert("host", "gateway:8080".parse().unwrap());
+        assert_eq!("http://gateway:8080", public_url(None, &headers));
+
+        headers.insert("forwarded", "for=192.0.2.60;proto=https;host=\"api.example.com\", for=198.51.100.17".parse().unwrap());
+        assert_eq!("https://api.example.com", public_url(None, &headers));
+
+        assert_eq!("https://docs.example.com/gateway", public_url(Some("https://docs.example.com/gateway/"), &headers));
+    }
+
+    #[test]
+    fn negotiate_json_or_yaml() {
+        assert_eq!(Some(ContentType::YAML), negotiate(None, ContentType::YAML));
+        assert_eq!(Some(ContentType::JSON), negotiate(Some("application/json,*/*"), ContentType::YAML));
+        assert_eq!(Some(ContentType::YAML), negotiate(Some("text/yaml"), ContentType::JSON));
+        assert_eq!(Some(ContentType::YAML), negotiate(Some("application/json;q=0.5, application/*"), ContentType::JSON));
+        assert_eq!(Some(ContentType::JSON), negotiate(Some("*/*"), ContentType::JSON));
+        assert_eq!(Some(ContentType::YAML), negotiate(Some("application/*;q=0.8, application/json;q=0"), ContentType::JSON));
+        assert_eq!(None, negotiate(Some("text/html"), ContentType::JSON));
+    }
+
+    #[tokio::test]
+    async fn serve_defs_as_json_or_yaml() {
+        let config = OpenApiConfig { name: "Pet Store".to_string(), ..Default::default() };
+        let entry = parse_openapi(ContentType::YAML, config, include_str!("../../fixtures/petstore-3.0.yaml").as_bytes()).unwrap();
+        let config = OpenApiConfig { name: "Pets ??".to_string(), ..Default::default() };
+        let other = parse_openapi(ContentType::YAML, config, include_str!("../../fixtures/petstore-3.0.yaml").as_bytes()).unwrap();
+        let router = Router::new()
+            .route("/docs/defs/*def", get(swagger_def_handler).layer(CompressionLayer::new()))
+            .with_state(AppState {
+                client: HttpClient::new(),
+                entries: Arc::new(RwLock::new(vec![entry, other])),
+                config: Arc::new(RwLock::new(toml::from_str("reload_cron = \"* * * * *\"\nopenapi_urls = []\n").unwrap())),
+                usage: Arc::default(),
+            });
+        let get = |uri: &str, headers: &[(&str, &str)]| {
+            let mut request = Request::get(uri);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            router.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        // Fetched as YAML
+        let response = get("/docs/defs/pet-store", &[]).await.unwrap();
+        assert_eq!("application/yaml", response.headers()["content-type"]);
+        let etag = response.headers()["etag"].clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains("\nopenapi: 3.0.3\n"));
+
+        let response = get("/docs/defs/pet-store", &[("accept", "application/json")]).await.unwrap();
+        assert_eq!("application/json", response.headers()["content-type"]);
+        assert_ne!(etag, response.headers()["etag"]);
+        let response = get("/docs/defs/pet-store.json", &[("accept", "application/yaml")]).await.unwrap();
+        assert_eq!("application/json", response.headers()["content-type"]);
+        let response = get("/docs/defs/pet-store", &[("accept", "text/html")]).await.unwrap();
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, response.status());
+
+        let response = get("/docs/defs/pet-store.yaml", &[("if-none-match", etag.to_str().unwrap())]).await.unwrap();
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+
+        let response = get("/docs/defs/pet-store.yaml", &[("accept-encoding", "gzip")]).await.unwrap();
+        assert_eq!("gzip", response.headers()["content-encoding"]);
+        assert_eq!(etag, response.headers()["etag"]);
+
+        // Base64 of the name, as served before slugs
+        let response = get("/docs/defs/UGV0IFN0b3Jl.yml", &[]).await.unwrap();
+        assert_eq!(StatusCode::MOVED_PERMANENTLY, response.status());
+        assert_eq!("/docs/defs/pet-store.yml", response.headers()["location"]);
+        let response = get("/docs/defs/UGV0cyA/Pw==", &[]).await.unwrap();
+        assert_eq!(StatusCode::MOVED_PERMANENTLY, response.status());
+        assert_eq!("/docs/defs/pets", response.headers()["location"]);
+
+        let response = get("/docs/defs/b3RoZXI=", &[]).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
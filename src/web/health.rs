@@ -0,0 +1,144 @@
+use crate::gateway::GatewayEntry;
+use crate::RwGatewayEntries;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct Liveness {
+    pub status: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Readiness {
+    pub status: &'static str,
+    /// Required entries without a parsed OpenAPI file
+    pub missing: Vec<String>,
+    pub entries: Vec<EntryReadiness>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EntryReadiness {
+    pub name: String,
+    pub optional: bool,
+    pub loaded: bool,
+    /// Loaded from an earlier fetch, the latest fetches failed
+    pub stale: bool,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+pub async fn liveness_handler() -> (StatusCode, Json<Liveness>) {
+    (StatusCode::OK, Json(Liveness { status: "alive" }))
+}
+
+pub async fn readiness_handler(
+    State(entries): State<RwGatewayEntries>,
+) -> (StatusCode, Json<Readiness>) {
+    let entries = entries.read().await;
+    let readiness = readiness(&entries);
+
+    let status = if readiness.missing.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(readiness))
+}
+
+fn readiness(entries: &[GatewayEntry]) -> Readiness {
+    let missing: Vec<String> = entries
+        .iter()
+        .filter(|entry| !entry.config.optional && !entry.is_loaded())
+        .map(|entry| entry.config.name.clone())
+        .collect();
+
+    Readiness {
+        status: if missing.is_empty() { "ready" } else { "not_ready" },
+        missing,
+        entries: entries
+            .iter()
+            .map(|entry| EntryReadiness {
+                name: entry.config.name.clone(),
+                optional: entry.config.optional,
+                loaded: entry.is_loaded(),
+                stale: entry.is_stale(),
+                last_success: entry.status.last_success,
+                last_error: entry.status.last_error.clone(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::OpenApiConfig;
+    use crate::gateway::{EntryStatus, GatewayEntry, OpenApiFile};
+    use crate::web::health::readiness;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn not_ready_while_required_entry_is_missing() {
+        let entries = vec![
+            loaded_entry("loaded"),
+            failed_entry(config("missing", false)),
+        ];
+
+        let readiness = readiness(&entries);
+
+        assert_eq!("not_ready", readiness.status);
+        assert_eq!(vec!["missing".to_string()], readiness.missing);
+        assert_eq!(Some("HTTP error".to_string()), readiness.entries[1].last_error);
+    }
+
+    #[test]
+    fn ready_when_only_optional_entries_are_missing() {
+        let entries = vec![
+            loaded_entry("loaded"),
+            failed_entry(config("optional", true)),
+        ];
+
+        let readiness = readiness(&entries);
+
+        assert_eq!("ready", readiness.status);
+        assert!(readiness.missing.is_empty());
+        assert!(readiness.entries[0].last_success.is_some());
+    }
+
+    fn loaded_entry(name: &str) -> GatewayEntry {
+        GatewayEntry {
+            config: config(name, false),
+            openapi_file: Some(OpenApiFile {
+                content_type: "application/json".to_string(),
+                contents: vec![],
+                content_hash: "".to_string(),
+                etag: None,
+                last_modified: None,
+                base_paths: vec![String::new()],
+                path_base_paths: Default::default(),
+                diagnostics: vec![],
+            }),
+            routes: vec![],
+            status: EntryStatus::succeeded(Utc::now()),
+            changes: Default::default(),
+        }
+    }
+
+    fn failed_entry(config: OpenApiConfig) -> GatewayEntry {
+        let mut entry = GatewayEntry::unloaded(config);
+        entry.status.record_failure("HTTP error".to_string(), Utc::now(), Duration::seconds(60));
+
+        entry
+    }
+
+    fn config(name: &str, optional: bool) -> OpenApiConfig {
+        OpenApiConfig {
+            name: name.to_string(),
+            optional,
+            ..Default::default()
+        }
+    }
+}